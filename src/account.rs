@@ -1,11 +1,15 @@
 use std::{
     collections::VecDeque,
     fmt::{self, Display},
+    str::FromStr,
 };
 
-use crate::money::MovementKind;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+use crate::{money::MovementKind, syntax::Span};
+
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Serialize, Deserialize)]
 pub enum AccountType {
     Assets,
     Liabilities,
@@ -14,7 +18,22 @@ pub enum AccountType {
     Expenses,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+impl FromStr for AccountType {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<Self, Self::Err> {
+        match v {
+            "assets" => Ok(AccountType::Assets),
+            "liabilities" => Ok(AccountType::Liabilities),
+            "income" => Ok(AccountType::Income),
+            "equity" => Ok(AccountType::Equity),
+            "expenses" => Ok(AccountType::Expenses),
+            _ => Err(format!("Invalid account type `{}`", v)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
 pub struct Account(pub AccountType, pub Vec<String>);
 
 impl Display for Account {
@@ -29,6 +48,32 @@ impl Display for Account {
     }
 }
 
+impl FromStr for Account {
+    type Err = String;
+
+    fn from_str(v: &str) -> Result<Self, Self::Err> {
+        let mut parts = v.split(':');
+
+        let kind = parts
+            .next()
+            .and_then(|kind| kind.parse::<AccountType>().ok())
+            .ok_or_else(|| format!("Invalid account type in `{}`", v))?;
+
+        let parts = parts.map(String::from).collect::<Vec<_>>();
+
+        if parts.is_empty() || parts.iter().any(|p| p.is_empty()) {
+            return Err(format!("Invalid account name `{}`", v));
+        }
+
+        Ok(Account(kind, parts))
+    }
+}
+
+/// Whether the account named `account` is `parent` itself or one of its children.
+pub fn is_same_or_child(account: &str, parent: &str) -> bool {
+    account == parent || (account.starts_with(parent) && account[parent.len()..].starts_with(':'))
+}
+
 impl Account {
     pub fn parts(&self) -> Vec<String> {
         let mut parts = VecDeque::from(self.1.clone());
@@ -70,3 +115,136 @@ impl Account {
         }
     }
 }
+
+/// An account that changed names on `date`, set with a `rename` op.
+#[derive(Debug, Clone)]
+pub struct Rename {
+    pub date: NaiveDate,
+    pub from: Account,
+    pub to: Account,
+    pub span: Span,
+}
+
+impl Rename {
+    pub fn new(date: NaiveDate, from: Account, to: Account, span: Span) -> Self {
+        Self {
+            date,
+            from,
+            to,
+            span,
+        }
+    }
+}
+
+/// Every rename in the ledger, used to map between canonical and historical account names.
+#[derive(Debug, Clone, Default)]
+pub struct AccountHistory {
+    pub renames: Vec<Rename>,
+}
+
+impl AccountHistory {
+    /// The latest name of `account`, following every rename regardless of date.
+    pub fn canonical(&self, account: &Account) -> Account {
+        let mut current = account.clone();
+
+        // Bounded so that rename cycles can't loop forever.
+        for _ in 0..=self.renames.len() {
+            match self.renames.iter().find(|r| r.from == current) {
+                Some(rename) => current = rename.to.clone(),
+                None => break,
+            }
+        }
+
+        current
+    }
+
+    /// The name a canonical account had on `date`.
+    pub fn name_at(&self, account: &Account, date: NaiveDate) -> Account {
+        let mut current = account.clone();
+
+        for _ in 0..=self.renames.len() {
+            match self
+                .renames
+                .iter()
+                .find(|r| r.to == current && r.date > date)
+            {
+                Some(rename) => current = rename.from.clone(),
+                None => break,
+            }
+        }
+
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_account_name() {
+        assert_eq!(
+            "expenses:food:market".parse::<Account>(),
+            Ok(Account(
+                AccountType::Expenses,
+                vec!["food".into(), "market".into()]
+            ))
+        );
+
+        assert!("expenses".parse::<Account>().is_err());
+        assert!("stuff:food".parse::<Account>().is_err());
+    }
+
+    #[test]
+    fn test_parent_matching() {
+        assert!(is_same_or_child("expenses:food", "expenses:food"));
+        assert!(is_same_or_child("expenses:food:market", "expenses:food"));
+        assert!(!is_same_or_child("expenses:foodstuff", "expenses:food"));
+    }
+
+    #[test]
+    fn test_account_history() {
+        let account = |name: &str| name.parse::<Account>().unwrap();
+
+        let history = AccountHistory {
+            renames: vec![
+                Rename::new(
+                    NaiveDate::from_ymd(2020, 1, 1),
+                    account("assets:itau"),
+                    account("assets:nubank"),
+                    0..1,
+                ),
+                Rename::new(
+                    NaiveDate::from_ymd(2022, 1, 1),
+                    account("assets:nubank"),
+                    account("assets:inter"),
+                    0..1,
+                ),
+            ],
+        };
+
+        assert_eq!(
+            history.canonical(&account("assets:itau")),
+            account("assets:inter")
+        );
+        assert_eq!(
+            history.canonical(&account("assets:cash")),
+            account("assets:cash")
+        );
+
+        let canonical = account("assets:inter");
+
+        assert_eq!(
+            history.name_at(&canonical, NaiveDate::from_ymd(2019, 6, 1)),
+            account("assets:itau")
+        );
+        assert_eq!(
+            history.name_at(&canonical, NaiveDate::from_ymd(2021, 6, 1)),
+            account("assets:nubank")
+        );
+        assert_eq!(
+            history.name_at(&canonical, NaiveDate::from_ymd(2022, 1, 1)),
+            account("assets:inter")
+        );
+    }
+}
@@ -0,0 +1,269 @@
+//! Reports of accounts that need attention because of how they are used: opened but never used,
+//! dormant while still holding money, or moved after their last balance assertion.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::{Datelike, NaiveDate};
+use polars::prelude::*;
+
+use crate::{
+    account::is_same_or_child, chart::AccountOpening, ledger::Ledger, options::LedgerOptions,
+    BalanceVerification,
+};
+
+/// The openings of accounts without movements, neither in them nor in the accounts below them.
+/// `used` has the name of every account with a movement.
+pub fn unused<'a>(opens: &'a [AccountOpening], used: &BTreeSet<String>) -> Vec<&'a AccountOpening> {
+    opens
+        .iter()
+        .filter(|o| {
+            let name = o.account.to_string();
+            !used.iter().any(|account| is_same_or_child(account, &name))
+        })
+        .collect()
+}
+
+/// The same day `months` months before `date`, or the last day of that month if it is shorter.
+fn months_before(date: NaiveDate, months: u32) -> NaiveDate {
+    let total = date.year() * 12 + date.month0() as i32 - months as i32;
+    let (year, month) = (total.div_euclid(12), total.rem_euclid(12) as u32 + 1);
+
+    (1..=date.day())
+        .rev()
+        .find_map(|day| NaiveDate::from_ymd_opt(year, month, day))
+        .unwrap_or_else(|| NaiveDate::from_ymd(year, month, 1))
+}
+
+/// The date of the last movement and the balance of each account, by currency.
+fn last_movements(ledger: &Ledger) -> Result<BTreeMap<(String, String), (NaiveDate, f64)>> {
+    let df = ledger.all()?;
+
+    let dates = df.column("ledger.date")?.date()?.as_date_iter();
+    let accounts = df.column("ledger.account_name")?.utf8()?;
+    let currencies = df.column("ledger.currency")?.utf8()?;
+    let amounts = df.column("ledger.signed_amount")?.f64()?;
+
+    let mut summaries: BTreeMap<_, (NaiveDate, f64)> = BTreeMap::new();
+
+    for (((date, account), currency), amount) in dates.zip(accounts).zip(currencies).zip(amounts) {
+        let (date, account, currency, amount) = match (date, account, currency, amount) {
+            (Some(date), Some(account), Some(currency), Some(amount)) => {
+                (date, account, currency, amount)
+            }
+            _ => continue,
+        };
+
+        let summary = summaries
+            .entry((account.to_string(), currency.to_string()))
+            .or_insert((date, 0.0));
+
+        summary.0 = summary.0.max(date);
+        summary.1 += amount;
+    }
+
+    Ok(summaries)
+}
+
+/// Lists every opened account without movements, sorted by name.
+pub fn unused_accounts(ledger: &Ledger, opens: &[AccountOpening]) -> Result<DataFrame> {
+    let used = last_movements(ledger)?
+        .into_keys()
+        .map(|(account, _)| account)
+        .collect();
+
+    let mut unused = unused(opens, &used);
+    unused.sort_by_key(|o| o.account.to_string());
+
+    DataFrame::new(vec![
+        Series::new(
+            "unused.account_name",
+            unused
+                .iter()
+                .map(|o| o.account.to_string())
+                .collect::<Vec<_>>(),
+        ),
+        DateChunked::new_from_naive_date(
+            "unused.opened_on",
+            &unused.iter().map(|o| o.date).collect::<Vec<_>>(),
+        )
+        .into_series(),
+    ])
+}
+
+/// Lists the accounts without movements in the `months` months before `today` that still hold
+/// a balance in some currency.
+pub fn dormant_accounts(
+    ledger: &Ledger,
+    months: u32,
+    today: NaiveDate,
+    options: &LedgerOptions,
+) -> Result<DataFrame> {
+    let since = months_before(today, months);
+
+    let dormant = last_movements(ledger)?
+        .into_iter()
+        .filter(|(_, (last, balance))| *last < since && !options.equals(*balance, 0.0))
+        .collect::<Vec<_>>();
+
+    DataFrame::new(vec![
+        Series::new(
+            "dormant.account_name",
+            dormant
+                .iter()
+                .map(|((account, _), _)| account.clone())
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "dormant.currency",
+            dormant
+                .iter()
+                .map(|((_, currency), _)| currency.clone())
+                .collect::<Vec<_>>(),
+        ),
+        DateChunked::new_from_naive_date(
+            "dormant.last_movement",
+            &dormant
+                .iter()
+                .map(|(_, (last, _))| *last)
+                .collect::<Vec<_>>(),
+        )
+        .into_series(),
+        Series::new(
+            "dormant.balance",
+            dormant
+                .iter()
+                .map(|(_, (_, balance))| *balance)
+                .collect::<Vec<_>>(),
+        ),
+    ])
+}
+
+/// Lists the accounts with movements after the last `balance` directive written for them, by
+/// currency. Accounts without any `balance` directive are not listed.
+pub fn unasserted_activity(
+    ledger: &Ledger,
+    verifications: &[BalanceVerification],
+) -> Result<DataFrame> {
+    let mut asserted: BTreeMap<(String, String), NaiveDate> = BTreeMap::new();
+
+    for verification in verifications {
+        let date = asserted
+            .entry((
+                verification.account.to_string(),
+                verification.amount.currency(),
+            ))
+            .or_insert(verification.date);
+
+        *date = (*date).max(verification.date);
+    }
+
+    let unasserted = last_movements(ledger)?
+        .into_iter()
+        .filter_map(|(key, (last, _))| {
+            asserted
+                .get(&key)
+                .filter(|asserted| last > **asserted)
+                .map(|asserted| (key, *asserted, last))
+        })
+        .collect::<Vec<_>>();
+
+    DataFrame::new(vec![
+        Series::new(
+            "unasserted.account_name",
+            unasserted
+                .iter()
+                .map(|((account, _), _, _)| account.clone())
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "unasserted.currency",
+            unasserted
+                .iter()
+                .map(|((_, currency), _, _)| currency.clone())
+                .collect::<Vec<_>>(),
+        ),
+        DateChunked::new_from_naive_date(
+            "unasserted.last_assertion",
+            &unasserted
+                .iter()
+                .map(|(_, date, _)| *date)
+                .collect::<Vec<_>>(),
+        )
+        .into_series(),
+        DateChunked::new_from_naive_date(
+            "unasserted.last_movement",
+            &unasserted
+                .iter()
+                .map(|(_, _, last)| *last)
+                .collect::<Vec<_>>(),
+        )
+        .into_series(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = r#"2020-01-01 open assets:bank BRL
+2020-01-01 open assets:cash BRL
+2020-01-01 open assets:safe BRL
+2020-01-01 open expenses:food
+2020-01-01 open income:salary
+2020-01-02 transaction "Salary"
+  > 1000 BRL income:salary
+  < 1000 BRL assets:bank:checking
+2020-01-03 balance assets:bank:checking 1000 BRL
+2020-03-01 transaction "Withdrawal"
+  > 100 BRL assets:bank:checking
+  < 100 BRL assets:cash
+2020-06-30 transaction "Market"
+  > 100 BRL assets:cash
+  < 100 BRL expenses:food
+"#;
+
+    fn column(df: &DataFrame, name: &str) -> Result<Vec<String>> {
+        Ok(df
+            .column(name)?
+            .utf8()?
+            .into_iter()
+            .map(|v| v.unwrap_or_default().to_string())
+            .collect())
+    }
+
+    #[test]
+    fn test_months_before() {
+        assert_eq!(
+            months_before(NaiveDate::from_ymd(2021, 3, 31), 1),
+            NaiveDate::from_ymd(2021, 2, 28)
+        );
+        assert_eq!(
+            months_before(NaiveDate::from_ymd(2021, 2, 15), 14),
+            NaiveDate::from_ymd(2019, 12, 15)
+        );
+    }
+
+    #[test]
+    fn test_account_activity() -> anyhow::Result<()> {
+        let program = crate::syntax::parse_string(std::path::Path::new("test.hta"), INPUT)?;
+        let (ledger, context) = crate::compute_program(program)?;
+
+        let unused = unused_accounts(&ledger, &context.opens)?;
+        assert_eq!(column(&unused, "unused.account_name")?, vec!["assets:safe"]);
+
+        let today = NaiveDate::from_ymd(2020, 7, 15);
+        let dormant = dormant_accounts(&ledger, 3, today, &context.options)?;
+        assert_eq!(
+            column(&dormant, "dormant.account_name")?,
+            vec!["assets:bank:checking", "income:salary"]
+        );
+
+        let unasserted = unasserted_activity(&ledger, &context.balance_verifications)?;
+        assert_eq!(
+            column(&unasserted, "unasserted.account_name")?,
+            vec!["assets:bank:checking"]
+        );
+
+        Ok(())
+    }
+}
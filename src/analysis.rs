@@ -0,0 +1,241 @@
+//! Queries about a single ledger file, like the ones editors make while it is being written.
+//!
+//! Offsets are character indices into the input, the same unit used by spans.
+
+use std::{collections::HashMap, path::Path};
+
+use anyhow::Result;
+
+use crate::{
+    account::{is_same_or_child, Account},
+    assertion::Query,
+    chart::{find_opening, AccountOpening},
+    compute_program,
+    diagnostic::Diagnostic,
+    ledger::Ledger,
+    syntax::{parse_string_recovery, Op, Span, Spanned},
+    validate::ValidationRunner,
+    LedgerContext,
+};
+
+pub struct Analysis {
+    /// Every problem found in the file, from parsing to `balance` directives.
+    pub diagnostics: Vec<Diagnostic>,
+    /// The ops that could be parsed, which is all of them when `parsed` is set.
+    ops: Vec<Spanned<Op>>,
+    parsed: bool,
+    ledger: Ledger,
+    context: LedgerContext,
+}
+
+impl Analysis {
+    pub fn new(filename: &Path, input: &str) -> Result<Self> {
+        let (ops, mut diagnostics) = parse_string_recovery(filename, input);
+        let parsed = diagnostics.is_empty();
+
+        let (ledger, context) = compute_program(ops.clone())?;
+
+        diagnostics.extend(ValidationRunner::default().run(filename, &ledger, &context)?);
+
+        Ok(Self {
+            diagnostics,
+            ops,
+            parsed,
+            ledger,
+            context,
+        })
+    }
+
+    /// Whether the whole file parsed. Queries still answer when it didn't, leaving out the ops
+    /// with syntax errors.
+    pub fn is_parsed(&self) -> bool {
+        self.parsed
+    }
+
+    /// The names of every opened account, sorted.
+    pub fn accounts(&self) -> Vec<String> {
+        let mut accounts = self
+            .opens()
+            .iter()
+            .map(|o| o.account.to_string())
+            .collect::<Vec<_>>();
+
+        accounts.sort();
+        accounts.dedup();
+        accounts
+    }
+
+    /// Every currency the ledger knows about, from `open` directives and movements, sorted.
+    pub fn currencies(&self) -> Result<Vec<String>> {
+        let mut currencies = self
+            .opens()
+            .iter()
+            .flat_map(|o| o.currencies.iter().map(|c| c.0.clone()))
+            .collect::<Vec<_>>();
+
+        currencies.extend(
+            self.ledger
+                .all()?
+                .column("ledger.currency")?
+                .utf8()?
+                .into_iter()
+                .flatten()
+                .map(String::from),
+        );
+
+        currencies.sort();
+        currencies.dedup();
+        Ok(currencies)
+    }
+
+    /// The account written at `offset`, by its current name, if there is one.
+    ///
+    /// Movements and assertions have no span for their account alone, so anywhere in them
+    /// counts.
+    pub fn account_at(&self, offset: usize) -> Option<Account> {
+        let mut candidates: Vec<(&Span, &Account)> = vec![];
+
+        for (op, _) in self.ops.iter() {
+            match op {
+                Op::Open(_, (account, span), _, _)
+                | Op::Balance(_, (account, span), _)
+                | Op::Budget((account, span), _, _)
+                | Op::Note(_, (account, span), _)
+                | Op::Document(_, (account, span), _)
+                | Op::Alias(_, (account, span)) => candidates.push((span, account)),
+                Op::Rename(_, (from, from_span), (to, to_span)) => {
+                    candidates.push((from_span, from));
+                    candidates.push((to_span, to));
+                }
+                Op::Transaction(_, _, (movements, _), _) => {
+                    candidates.extend(movements.iter().map(|(m, span)| (span, &m.2)))
+                }
+                Op::Assert(_, (assertion, span)) => match &assertion.query {
+                    Query::Sum(pattern, _) | Query::Balance(pattern) => {
+                        candidates.push((span, &pattern.account))
+                    }
+                    Query::Count(..) => {}
+                },
+                Op::Option(_) => {}
+            }
+        }
+
+        let (_, account) = candidates
+            .into_iter()
+            .filter(|(span, _)| span.contains(&offset))
+            .min_by_key(|(span, _)| span.len())?;
+
+        Some(self.context.history.canonical(account))
+    }
+
+    /// The `open` directive of `account`.
+    pub fn opening(&self, account: &Account) -> Option<&AccountOpening> {
+        find_opening(self.opens(), &account.to_string())
+    }
+
+    /// The balance of `account` and its children in each currency, counting every movement
+    /// written before `offset`.
+    pub fn balance_at(&self, account: &Account, offset: usize) -> Result<Vec<(String, f64)>> {
+        let name = account.to_string();
+        let df = self.ledger.all()?;
+
+        let accounts = df.column("ledger.account_name")?.utf8()?;
+        let currencies = df.column("ledger.currency")?.utf8()?;
+        let amounts = df.column("ledger.signed_amount")?.f64()?;
+        let starts = df.column("ledger.span_start")?.u64()?;
+
+        let mut balances: HashMap<String, f64> = HashMap::new();
+
+        for (((acc, currency), amount), start) in accounts
+            .into_iter()
+            .zip(currencies)
+            .zip(amounts)
+            .zip(starts)
+        {
+            match (acc, currency, amount, start) {
+                (Some(acc), Some(currency), Some(amount), Some(start))
+                    if is_same_or_child(acc, &name) && (start as usize) < offset =>
+                {
+                    *balances.entry(currency.to_string()).or_insert(0.0) += amount;
+                }
+                _ => {}
+            }
+        }
+
+        let mut balances = balances.into_iter().collect::<Vec<_>>();
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(balances)
+    }
+
+    /// Everything the ledger defines besides transactions.
+    pub fn context(&self) -> &LedgerContext {
+        &self.context
+    }
+
+    fn opens(&self) -> &[AccountOpening] {
+        &self.context.opens
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = r#"2020-01-01 open assets:cash BRL
+2020-01-01 open equity:initial_import
+2020-01-02 transaction "Initial import"
+  > 100 BRL equity:initial_import
+  < 100 BRL assets:cash
+2020-01-03 transaction "Dollars"
+  > 10 USD equity:initial_import
+  < 10 USD assets:cash
+"#;
+
+    fn offset_of(needle: &str, nth: usize) -> usize {
+        let byte = INPUT.match_indices(needle).nth(nth).unwrap().0;
+        INPUT[..byte].chars().count()
+    }
+
+    #[test]
+    fn test_completions() -> Result<()> {
+        let analysis = Analysis::new(Path::new("test.hta"), INPUT)?;
+
+        assert_eq!(
+            analysis.accounts(),
+            vec!["assets:cash", "equity:initial_import"]
+        );
+        assert_eq!(analysis.currencies()?, vec!["BRL", "USD"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_account_at() -> Result<()> {
+        let analysis = Analysis::new(Path::new("test.hta"), INPUT)?;
+        let account = analysis.account_at(offset_of("assets:cash", 1)).unwrap();
+
+        assert_eq!(account.to_string(), "assets:cash");
+        assert_eq!(analysis.opening(&account).map(|o| o.span.start), Some(0));
+        assert!(analysis.account_at(offset_of("Initial", 0)).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_balance_at() -> Result<()> {
+        let analysis = Analysis::new(Path::new("test.hta"), INPUT)?;
+        let cash: Account = "assets:cash".parse().unwrap();
+
+        assert_eq!(
+            analysis.balance_at(&cash, offset_of("2020-01-03", 0))?,
+            vec![("BRL".to_string(), 100.0)]
+        );
+        assert_eq!(
+            analysis.balance_at(&cash, INPUT.len())?,
+            vec![("BRL".to_string(), 100.0), ("USD".to_string(), 10.0)]
+        );
+
+        Ok(())
+    }
+}
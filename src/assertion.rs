@@ -0,0 +1,403 @@
+//! Assertions written with `assert` ops, like `assert balance(assets:bank:*) >= 0 BRL`, and
+//! checked against the ledger once it was computed.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Display},
+    path::Path,
+};
+
+use chrono::{Datelike, NaiveDate};
+use num::{BigRational, ToPrimitive};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::{is_same_or_child, Account, AccountHistory},
+    codes,
+    diagnostic::Diagnostic,
+    ledger::Ledger,
+    money::Money,
+    options::LedgerOptions,
+    syntax::{printer::Quoted, Metadata, Span},
+    utils::format_decimal,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    /// Whether `found` compares to `expected` this way. Values the ledger's options consider
+    /// equal are equal.
+    pub fn holds(&self, found: f64, expected: f64, options: &LedgerOptions) -> bool {
+        let equal = options.equals(found, expected);
+
+        match self {
+            Self::Less => found < expected && !equal,
+            Self::LessOrEqual => found < expected || equal,
+            Self::Greater => found > expected && !equal,
+            Self::GreaterOrEqual => found > expected || equal,
+            Self::Equal => equal,
+            Self::NotEqual => !equal,
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Less => write!(f, "<"),
+            Self::LessOrEqual => write!(f, "<="),
+            Self::Greater => write!(f, ">"),
+            Self::GreaterOrEqual => write!(f, ">="),
+            Self::Equal => write!(f, "=="),
+            Self::NotEqual => write!(f, "!="),
+        }
+    }
+}
+
+/// An account along with the ones below it, or only the ones below it when written with a
+/// trailing `:*`.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct AccountPattern {
+    pub account: Account,
+    pub children_only: bool,
+}
+
+impl AccountPattern {
+    pub fn matches(&self, name: &str) -> bool {
+        let account = self.account.to_string();
+
+        is_same_or_child(name, &account) && !(self.children_only && name == account)
+    }
+}
+
+impl Display for AccountPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.account)?;
+
+        if self.children_only {
+            write!(f, ":*")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The year or month that a `sum` adds up.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+pub enum AssertionPeriod {
+    Year(i32),
+    Month(i32, u32),
+}
+
+impl AssertionPeriod {
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        match self {
+            Self::Year(year) => date.year() == *year,
+            Self::Month(year, month) => date.year() == *year && date.month() == *month,
+        }
+    }
+}
+
+impl Display for AssertionPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Year(year) => write!(f, "{}", year),
+            Self::Month(year, month) => write!(f, "{}-{:02}", year, month),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub enum Query {
+    /// What the movements of the accounts add up to, only counting the ones within a period
+    /// when there is one.
+    Sum(AccountPattern, Option<AssertionPeriod>),
+    Balance(AccountPattern),
+    /// The number of transactions with a metadata key, and a specific value for it when there
+    /// is one.
+    Count(String, Option<String>),
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sum(pattern, Some(period)) => write!(f, "sum({}, {})", pattern, period),
+            Self::Sum(pattern, None) => write!(f, "sum({})", pattern),
+            Self::Balance(pattern) => write!(f, "balance({})", pattern),
+            Self::Count(key, Some(value)) => write!(f, "count(tag:{}, {})", key, Quoted(value)),
+            Self::Count(key, None) => write!(f, "count(tag:{})", key),
+        }
+    }
+}
+
+/// What a query is compared with: an amount for sums and balances, and a plain number for
+/// counts.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub enum Bound {
+    Amount(Money),
+    Number(BigRational),
+}
+
+impl Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Amount(money) => write!(f, "{}", money),
+            Self::Number(n) => write!(f, "{}", format_decimal(n)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub struct Assertion {
+    pub query: Query,
+    pub comparison: Comparison,
+    pub bound: Bound,
+}
+
+impl Assertion {
+    /// The same assertion, with accounts by their current name.
+    pub fn canonical(self, history: &AccountHistory) -> Self {
+        let canonical = |pattern: AccountPattern| AccountPattern {
+            account: history.canonical(&pattern.account),
+            ..pattern
+        };
+
+        let query = match self.query {
+            Query::Sum(pattern, period) => Query::Sum(canonical(pattern), period),
+            Query::Balance(pattern) => Query::Balance(canonical(pattern)),
+            query @ Query::Count(..) => query,
+        };
+
+        Self { query, ..self }
+    }
+}
+
+impl Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.query, self.comparison, self.bound)
+    }
+}
+
+/// An `assert` op, checked against the ledger up to its date, or against all of it when it has
+/// none.
+#[derive(Debug, Clone)]
+pub struct LedgerAssertion {
+    pub date: Option<NaiveDate>,
+    pub assertion: Assertion,
+    pub span: Span,
+}
+
+impl LedgerAssertion {
+    pub fn new(date: Option<NaiveDate>, assertion: Assertion, span: Span) -> Self {
+        Self {
+            date,
+            assertion,
+            span,
+        }
+    }
+
+    /// The value of the query. Sums and balances only count movements in the currency of the
+    /// amount they are compared with.
+    pub fn evaluate(
+        &self,
+        ledger: &Ledger,
+        transaction_metadata: &HashMap<u64, Metadata>,
+    ) -> Result<f64> {
+        let df = ledger.all()?;
+
+        let parents = df.column("ledger.parent_id")?.u64()?;
+        let dates = df.column("ledger.date")?.date()?.as_date_iter();
+        let accounts = df.column("ledger.account_name")?.utf8()?;
+        let currencies = df.column("ledger.currency")?.utf8()?;
+        let amounts = df.column("ledger.signed_amount")?.f64()?;
+
+        let currency = match &self.assertion.bound {
+            Bound::Amount(money) => Some(money.currency()),
+            Bound::Number(_) => None,
+        };
+
+        let mut sum = 0.0;
+        let mut counted = BTreeSet::new();
+
+        for ((((parent, date), account), cur), amount) in parents
+            .into_iter()
+            .zip(dates)
+            .zip(accounts)
+            .zip(currencies)
+            .zip(amounts)
+        {
+            let (parent, date, account, cur, amount) = match (parent, date, account, cur, amount) {
+                (Some(p), Some(d), Some(a), Some(c), Some(v)) => (p, d, a, c, v),
+                _ => continue,
+            };
+
+            if self.date.is_some_and(|until| date > until) {
+                continue;
+            }
+
+            let in_currency = currency.as_deref() == Some(cur);
+
+            match &self.assertion.query {
+                Query::Sum(pattern, period) => {
+                    if in_currency
+                        && pattern.matches(account)
+                        && period.is_none_or(|p| p.contains(date))
+                    {
+                        sum += amount;
+                    }
+                }
+                Query::Balance(pattern) => {
+                    if in_currency && pattern.matches(account) {
+                        sum += amount;
+                    }
+                }
+                Query::Count(key, value) => {
+                    let tagged = transaction_metadata.get(&parent).is_some_and(|metadata| {
+                        metadata
+                            .iter()
+                            .any(|(k, v)| k == key && value.as_ref().is_none_or(|x| x == v))
+                    });
+
+                    if tagged {
+                        counted.insert(parent);
+                    }
+                }
+            }
+        }
+
+        match self.assertion.query {
+            Query::Count(..) => Ok(counted.len() as f64),
+            _ => Ok(sum),
+        }
+    }
+
+    /// Compares the assertion with `value`, the value of its query.
+    pub fn check(
+        &self,
+        filename: &Path,
+        value: f64,
+        options: &LedgerOptions,
+    ) -> Option<Diagnostic> {
+        let (expected, found) = match &self.assertion.bound {
+            Bound::Amount(money) => (
+                money.amount.to_f64().unwrap_or(f64::NAN),
+                format!(
+                    "{:.2$} {}",
+                    value,
+                    money.currency(),
+                    options.display_precision as usize
+                ),
+            ),
+            Bound::Number(n) => (n.to_f64().unwrap_or(f64::NAN), value.to_string()),
+        };
+
+        if self.assertion.comparison.holds(value, expected, options) {
+            return None;
+        }
+
+        let until = self.date.map(|d| format!(" on {}", d)).unwrap_or_default();
+
+        Some(
+            Diagnostic::error(
+                codes::ASSERTION_FAILED,
+                format!(
+                    "Assertion does not hold{}, expected `{}`, found {}",
+                    until, self.assertion, found
+                ),
+            )
+            .in_file(filename)
+            .with_label(
+                self.span.clone(),
+                format!("`{}` is {} at this point", self.assertion.query, found),
+            )
+            .with_span(self.span.clone()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = r#"2021-01-01 open assets:bank:checking BRL
+2021-01-01 open expenses:food
+2021-01-01 open income:salary
+2021-01-05 transaction "Salary"
+  > 30000 BRL income:salary
+  < 30000 BRL assets:bank:checking
+2021-03-02 transaction "Market"
+  reimbursable: "open"
+  > 15000 BRL assets:bank:checking
+  < 15000 BRL expenses:food
+2021-06-01 transaction "Restaurant"
+  reimbursable: "paid"
+  > 8000 BRL assets:bank:checking
+  < 8000 BRL expenses:food:restaurants
+2022-01-10 transaction "Market"
+  > 9000 BRL assets:bank:checking
+  < 9000 BRL expenses:food
+assert 2021-12-31 sum(expenses:food, 2021) < 20000 BRL
+assert sum(expenses:food:*, 2021-06) == 8000 BRL
+assert count(tag:reimbursable, open) == 0
+assert count(tag:reimbursable) == 2
+assert 2021-12-31 balance(assets:bank:*) >= 0 BRL
+assert balance(assets:bank:*) >= 0 BRL
+assert balance(assets:bank:*) >= 0 USD
+"#;
+
+    #[test]
+    fn test_assertions() -> anyhow::Result<()> {
+        let filename = Path::new("test.hta");
+        let program = crate::syntax::parse_string(filename, INPUT)?;
+        let (ledger, context) = crate::compute_program(program)?;
+
+        let results = context
+            .assertions
+            .iter()
+            .map(|a| {
+                let value = a.evaluate(&ledger, &context.transaction_metadata)?;
+                let failed = a.check(filename, value, &context.options).is_some();
+
+                Ok((value, failed))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            results,
+            vec![
+                (23000.0, true),
+                (8000.0, false),
+                (1.0, true),
+                (2.0, false),
+                (7000.0, false),
+                (-2000.0, true),
+                (0.0, false),
+            ]
+        );
+
+        let diagnostic = context.assertions[0]
+            .check(filename, results[0].0, &context.options)
+            .unwrap();
+
+        assert_eq!(diagnostic.code, codes::ASSERTION_FAILED);
+        assert_eq!(
+            diagnostic.message,
+            "Assertion does not hold on 2021-12-31, expected `sum(expenses:food, 2021) < 20000 BRL`, found 23000.00 BRL"
+        );
+        assert_eq!(
+            diagnostic.span.map(|s| INPUT[s].trim_end()),
+            Some("assert 2021-12-31 sum(expenses:food, 2021) < 20000 BRL")
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::{bail, Result};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Options {
+    /// A diagnostic code, like `H0101`. Lists every code when omitted.
+    #[structopt(name = "code")]
+    code: Option<String>,
+}
+
+use hortela::codes::{explain, ALL_CODES};
+
+fn main() -> Result<()> {
+    let options = Options::from_args();
+
+    let code = match options.code {
+        Some(code) => code,
+        None => {
+            for info in ALL_CODES {
+                println!("{}\t{}", info.code, info.title);
+            }
+
+            return Ok(());
+        }
+    };
+
+    match explain(&code) {
+        Some(info) => {
+            println!("{}: {}\n", info.code, info.title);
+            print!("{}", info.explanation);
+        }
+        None => bail!("`{}` is not a hortela diagnostic code", code),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct Options {
+    /// Only check whether the files are formatted, without changing them.
+    #[structopt(long)]
+    check: bool,
+    #[structopt(name = "files", required = true)]
+    files: Vec<PathBuf>,
+}
+
+use hortela::syntax::{self, format::format_source};
+
+fn main() -> Result<()> {
+    let options = Options::from_args();
+    let mut unformatted = vec![];
+
+    for file in options.files.iter() {
+        let input = std::fs::read_to_string(file)?;
+
+        // Refuse to rewrite files that do not parse, so errors are not moved around.
+        syntax::parse_string(file, &input).map_err(|diagnostics| {
+            diagnostics.eprint(&input);
+            diagnostics
+        })?;
+
+        let formatted = format_source(&input);
+
+        if formatted == input {
+            continue;
+        }
+
+        if options.check {
+            println!("{} is not formatted", file.display());
+            unformatted.push(file);
+        } else {
+            std::fs::write(file, formatted)?;
+        }
+    }
+
+    if !unformatted.is_empty() {
+        bail!("{} file(s) need formatting", unformatted.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,373 @@
+//! A language server for ledger files, talking LSP over stdin and stdout.
+//!
+//! LSP counts columns in UTF-16 code units, while spans count characters, so positions are
+//! converted using the text of their line.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::Result;
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as LspNotification, PublishDiagnostics,
+    },
+    request::{Completion, Formatting, GotoDefinition, HoverRequest, Request as LspRequest},
+    CompletionItem, CompletionItemKind, CompletionResponse, DiagnosticSeverity,
+    DocumentFormattingParams, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverParams, HoverProviderCapability, Location, MarkupContent, MarkupKind, NumberOrString,
+    OneOf, Position as LspPosition, PublishDiagnosticsParams, Range, ServerCapabilities,
+    TextDocumentSyncCapability, TextDocumentSyncKind, TextEdit, Url,
+};
+
+use hortela::{
+    analysis::Analysis,
+    diagnostic::{Diagnostic, Position, Severity},
+    syntax::{format::format_source, Span},
+};
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+    accounts: Vec<String>,
+    currencies: Vec<String>,
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Url, Document>,
+}
+
+/// The text of the 1-based `line` of `text`, without its newline.
+fn line_text(text: &str, line: usize) -> &str {
+    text.split('\n').nth(line - 1).unwrap_or("")
+}
+
+fn to_lsp_position(text: &str, position: Position) -> LspPosition {
+    let character: usize = line_text(text, position.line)
+        .chars()
+        .take(position.column - 1)
+        .map(char::len_utf16)
+        .sum();
+
+    LspPosition::new(position.line as u32 - 1, character as u32)
+}
+
+/// The character offset of `position`. Columns past the end of the line stop at the newline, and
+/// ones in the middle of a character stop before it.
+fn to_offset(text: &str, position: LspPosition) -> usize {
+    let mut units = 0;
+    let column = line_text(text, position.line as usize + 1)
+        .chars()
+        .take_while(|c| {
+            units += c.len_utf16();
+            units <= position.character as usize
+        })
+        .count();
+
+    Position {
+        line: position.line as usize + 1,
+        column: column + 1,
+    }
+    .offset_in(text)
+}
+
+fn to_range(text: &str, span: &Span) -> Range {
+    Range::new(
+        to_lsp_position(text, Position::of(text, span.start)),
+        to_lsp_position(text, Position::of(text, span.end)),
+    )
+}
+
+fn to_lsp_diagnostic(text: &str, diagnostic: &Diagnostic) -> lsp_types::Diagnostic {
+    let range = diagnostic
+        .range(text)
+        .map(|(start, end)| Range::new(to_lsp_position(text, start), to_lsp_position(text, end)))
+        .unwrap_or_default();
+
+    let severity = match diagnostic.severity {
+        Severity::Error => DiagnosticSeverity::ERROR,
+        Severity::Warning => DiagnosticSeverity::WARNING,
+        Severity::Note => DiagnosticSeverity::INFORMATION,
+    };
+
+    let mut message = diagnostic.message.clone();
+
+    for note in diagnostic.notes.iter() {
+        message.push_str("\n");
+        message.push_str(note);
+    }
+
+    lsp_types::Diagnostic {
+        range,
+        severity: Some(severity),
+        code: Some(NumberOrString::String(diagnostic.code.to_string())),
+        source: Some("hortela".into()),
+        message,
+        ..Default::default()
+    }
+}
+
+fn file_path(uri: &Url) -> PathBuf {
+    uri.to_file_path()
+        .unwrap_or_else(|_| PathBuf::from(uri.path()))
+}
+
+impl Server {
+    fn update(&mut self, connection: &Connection, uri: Url, text: String) -> Result<()> {
+        let analysis = Analysis::new(&file_path(&uri), &text)?;
+
+        // Ops with syntax errors are left out, so completions keep working while typing.
+        let (accounts, currencies) = (analysis.accounts(), analysis.currencies()?);
+
+        let diagnostics = analysis
+            .diagnostics
+            .iter()
+            .map(|d| to_lsp_diagnostic(&text, d))
+            .collect();
+
+        let params = PublishDiagnosticsParams::new(uri.clone(), diagnostics, None);
+        connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                PublishDiagnostics::METHOD.into(),
+                params,
+            )))?;
+
+        self.documents.insert(
+            uri,
+            Document {
+                text,
+                analysis,
+                accounts,
+                currencies,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn completion(&self, uri: &Url) -> Option<CompletionResponse> {
+        let document = self.documents.get(uri)?;
+
+        let accounts = document.accounts.iter().map(|a| CompletionItem {
+            label: a.clone(),
+            kind: Some(CompletionItemKind::FIELD),
+            detail: Some("account".into()),
+            ..Default::default()
+        });
+
+        let currencies = document.currencies.iter().map(|c| CompletionItem {
+            label: c.clone(),
+            kind: Some(CompletionItemKind::UNIT),
+            detail: Some("currency".into()),
+            ..Default::default()
+        });
+
+        Some(CompletionResponse::Array(
+            accounts.chain(currencies).collect(),
+        ))
+    }
+
+    fn hover(&self, params: HoverParams) -> Result<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let document = match self.documents.get(&position.text_document.uri) {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+
+        let offset = to_offset(&document.text, position.position);
+        let account = match document.analysis.account_at(offset) {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+
+        // Everything written up to the end of the hovered line counts towards the balance.
+        let end_of_line = to_offset(
+            &document.text,
+            LspPosition::new(position.position.line, u32::MAX),
+        );
+        let precision = document.analysis.context().options.display_precision as usize;
+
+        let balances = document
+            .analysis
+            .balance_at(&account, end_of_line + 1)?
+            .into_iter()
+            .map(|(currency, amount)| format!("- {:.*} {}", precision, amount, currency))
+            .collect::<Vec<_>>();
+
+        let balance = if balances.is_empty() {
+            "No movements yet.".to_string()
+        } else {
+            balances.join("\n")
+        };
+
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("**{}**\n\nBalance as of this line:\n\n{}", account, balance),
+            }),
+            range: None,
+        }))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let document = self.documents.get(&uri)?;
+
+        let offset = to_offset(&document.text, position.position);
+        let account = document.analysis.account_at(offset)?;
+        let opening = document.analysis.opening(&account)?;
+
+        Some(GotoDefinitionResponse::Scalar(Location::new(
+            uri,
+            to_range(&document.text, &opening.span),
+        )))
+    }
+
+    fn formatting(&self, params: DocumentFormattingParams) -> Option<Vec<TextEdit>> {
+        let document = self.documents.get(&params.text_document.uri)?;
+
+        // Only files that parse are formatted, so that a typo can't move things around.
+        if !document.analysis.is_parsed() {
+            return None;
+        }
+
+        let formatted = format_source(&document.text);
+
+        if formatted == document.text {
+            return Some(vec![]);
+        }
+
+        let end = Position::of(&document.text, document.text.chars().count());
+        let range = Range::new(LspPosition::new(0, 0), to_lsp_position(&document.text, end));
+
+        Some(vec![TextEdit::new(range, formatted)])
+    }
+
+    fn handle_request(&self, request: Request) -> Result<Response> {
+        let id: RequestId = request.id.clone();
+
+        let result = match request.method.as_str() {
+            Completion::METHOD => {
+                let (_, params) =
+                    request.extract::<lsp_types::CompletionParams>(Completion::METHOD)?;
+                serde_json::to_value(
+                    self.completion(&params.text_document_position.text_document.uri),
+                )?
+            }
+            HoverRequest::METHOD => {
+                let (_, params) = request.extract::<HoverParams>(HoverRequest::METHOD)?;
+                serde_json::to_value(self.hover(params)?)?
+            }
+            GotoDefinition::METHOD => {
+                let (_, params) =
+                    request.extract::<GotoDefinitionParams>(GotoDefinition::METHOD)?;
+                serde_json::to_value(self.definition(params))?
+            }
+            Formatting::METHOD => {
+                let (_, params) =
+                    request.extract::<DocumentFormattingParams>(Formatting::METHOD)?;
+                serde_json::to_value(self.formatting(params))?
+            }
+            _ => {
+                return Ok(Response::new_err(
+                    id,
+                    lsp_server::ErrorCode::MethodNotFound as i32,
+                    format!("Unsupported request `{}`", request.method),
+                ))
+            }
+        };
+
+        Ok(Response::new_ok(id, result))
+    }
+
+    fn handle_notification(
+        &mut self,
+        connection: &Connection,
+        notification: Notification,
+    ) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params = notification
+                    .extract::<lsp_types::DidOpenTextDocumentParams>(DidOpenTextDocument::METHOD)?;
+                self.update(
+                    connection,
+                    params.text_document.uri,
+                    params.text_document.text,
+                )?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let mut params = notification.extract::<lsp_types::DidChangeTextDocumentParams>(
+                    DidChangeTextDocument::METHOD,
+                )?;
+
+                // Documents are synchronized in full, so the last change has the whole text.
+                if let Some(change) = params.content_changes.pop() {
+                    self.update(connection, params.text_document.uri, change.text)?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params = notification.extract::<lsp_types::DidCloseTextDocumentParams>(
+                    DidCloseTextDocument::METHOD,
+                )?;
+                self.documents.remove(&params.text_document.uri);
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+fn main() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(Default::default()),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    };
+
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server::default();
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    break;
+                }
+
+                let id = request.id.clone();
+                let response = server.handle_request(request).unwrap_or_else(|e| {
+                    Response::new_err(
+                        id,
+                        lsp_server::ErrorCode::InternalError as i32,
+                        e.to_string(),
+                    )
+                });
+
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                // A broken document must not take the whole server down with it.
+                if let Err(e) = server.handle_notification(&connection, notification) {
+                    eprintln!("Error: {:?}", e);
+                }
+            }
+            Message::Response(_) => {}
+        }
+    }
+
+    // The writer thread only stops once every sender is gone.
+    drop(connection);
+    io_threads.join()?;
+
+    Ok(())
+}
@@ -1,10 +1,19 @@
-use std::path::{Path, PathBuf};
+use std::path::PathBuf;
 
 use anyhow::Result;
+use chrono::{Local, NaiveDate};
 use polars::prelude::*;
 use structopt::StructOpt;
 
-use hortela::{compute_program, syntax};
+use hortela::{
+    account::Account,
+    activity::{dormant_accounts, unasserted_activity, unused_accounts},
+    budget::{budget_vs_actual, Period},
+    chart::chart_of_accounts,
+    compute_program,
+    register::register,
+    syntax,
+};
 
 #[derive(StructOpt)]
 pub struct Options {
@@ -16,6 +25,12 @@ pub struct Options {
 pub struct GlobalOptions {
     #[structopt(name = "file")]
     file: PathBuf,
+    /// Only report amounts in this currency, defaults to the ledger's operating currency.
+    #[structopt(long)]
+    currency: Option<String>,
+    /// Show accounts by the name they had at the time, instead of their current name.
+    #[structopt(long)]
+    historical_names: bool,
 }
 
 #[derive(StructOpt)]
@@ -25,27 +40,73 @@ pub enum Reporter {
         #[structopt(flatten)]
         global: GlobalOptions,
     },
+    #[structopt(name = "budget")]
+    Budget {
+        #[structopt(flatten)]
+        global: GlobalOptions,
+        #[structopt(long, default_value = "monthly")]
+        period: Period,
+    },
+    #[structopt(name = "accounts")]
+    ChartOfAccounts {
+        #[structopt(flatten)]
+        global: GlobalOptions,
+    },
+    #[structopt(name = "register")]
+    Register {
+        #[structopt(flatten)]
+        global: GlobalOptions,
+        #[structopt(name = "account")]
+        account: Account,
+    },
+    /// Accounts opened but never used.
+    #[structopt(name = "unused")]
+    Unused {
+        #[structopt(flatten)]
+        global: GlobalOptions,
+    },
+    /// Accounts without movements in the last months that still hold a balance.
+    #[structopt(name = "dormant")]
+    Dormant {
+        #[structopt(flatten)]
+        global: GlobalOptions,
+        #[structopt(long, default_value = "6")]
+        months: u32,
+        /// Count the months back from this day instead of the current one, like `2021-03-10`.
+        #[structopt(long)]
+        today: Option<NaiveDate>,
+    },
+    /// Accounts with movements after their last balance assertion.
+    #[structopt(name = "unasserted")]
+    Unasserted {
+        #[structopt(flatten)]
+        global: GlobalOptions,
+    },
 }
 
 impl Reporter {
-    pub fn file(&self) -> &Path {
+    pub fn global(&self) -> &GlobalOptions {
         match self {
-            Self::BalanceSheet {
-                global: GlobalOptions { file },
-            } => file,
+            Self::BalanceSheet { global }
+            | Self::Budget { global, .. }
+            | Self::ChartOfAccounts { global }
+            | Self::Register { global, .. }
+            | Self::Unused { global }
+            | Self::Dormant { global, .. }
+            | Self::Unasserted { global } => global,
         }
     }
 }
 
-fn sums_by_account(df: &DataFrame, amount_column_name: &str) -> Result<DataFrame> {
+fn sums_by_account(
+    df: &DataFrame,
+    account_column_name: &str,
+    amount_column_name: &str,
+) -> Result<DataFrame> {
     let mut sums = df
         .clone()
-        .select(&[
-            "ledger.account_name",
-            "ledger.amount",
-            "ledger.signed_amount",
-        ])?
-        .groupby("ledger.account_name")?
+        .select(&[account_column_name, "ledger.amount", "ledger.signed_amount"])?
+        .groupby(account_column_name)?
         .sum()?;
 
     sums.rename(
@@ -61,14 +122,118 @@ fn sums_by_account(df: &DataFrame, amount_column_name: &str) -> Result<DataFrame
     Ok(sums)
 }
 
+fn only_currency(df: DataFrame, column: &str, currency: Option<&str>) -> Result<DataFrame> {
+    let currency = match currency {
+        Some(currency) => currency,
+        None => return Ok(df),
+    };
+
+    let mask = df
+        .column(column)?
+        .utf8()?
+        .into_iter()
+        .map(|c| c.map(|c| c == currency).unwrap_or(true))
+        .collect::<BooleanChunked>();
+
+    Ok(df.filter(&mask)?)
+}
+
+fn print_frame(df: &DataFrame, precision: usize) {
+    println!("{}", df.get_column_names().join("\t"));
+
+    for i in 0..df.height() {
+        if let Some(row) = df.get(i) {
+            let values = row
+                .iter()
+                .map(|v| match v {
+                    AnyValue::Float64(f) => format!("{:.*}", precision, f),
+                    v => v.to_string(),
+                })
+                .collect::<Vec<_>>();
+            println!("{}", values.join("\t"));
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let options = Options::from_args();
-    let (ledger, _) = compute_program(syntax::parse_file(options.reporter.file())?)?;
+    let global = options.reporter.global();
+    let input = std::fs::read_to_string(&global.file)?;
+
+    let parsed = syntax::parse_string(&global.file, &input).map_err(|diagnostics| {
+        diagnostics.eprint(&input);
+        diagnostics
+    })?;
+    let (ledger, context) = compute_program(parsed)?;
+
+    let currency = global
+        .currency
+        .clone()
+        .or_else(|| context.options.operating_currency.clone().map(String::from));
+    let currency = currency.as_deref();
+    let precision = context.options.display_precision as usize;
+    let (account_column, hidden_account_column) = if global.historical_names {
+        ("historical_account_name", "account_name")
+    } else {
+        ("account_name", "historical_account_name")
+    };
+
+    match options.reporter {
+        Reporter::BalanceSheet { .. } => {
+            let credits = only_currency(ledger.credits()?, "ledger.currency", currency)?;
+            let debits = only_currency(ledger.debits()?, "ledger.currency", currency)?;
+
+            let account_column = format!("ledger.{}", account_column);
+
+            let credits = sums_by_account(&credits, &account_column, "credits")?;
+            let debits = sums_by_account(&debits, &account_column, "debits")?;
 
-    let credits = sums_by_account(&ledger.credits()?, "credits")?;
-    let debits = sums_by_account(&ledger.debits()?, "debits")?;
+            print_frame(
+                &credits.left_join(&debits, account_column.as_str(), account_column.as_str())?,
+                precision,
+            );
+        }
+        Reporter::Budget { period, .. } => {
+            let report = budget_vs_actual(&ledger, &context.budgets, period, &context.options)?;
+
+            print_frame(
+                &only_currency(report, "budget.currency", currency)?,
+                precision,
+            );
+        }
+        Reporter::ChartOfAccounts { .. } => {
+            print_frame(&chart_of_accounts(&context.opens)?, precision);
+        }
+        Reporter::Register { ref account, .. } => {
+            let report = register(&ledger, &context, account)?
+                .drop(&format!("register.{}", hidden_account_column))?;
 
-    dbg!(credits.left_join(&debits, "ledger.account_name", "ledger.account_name")?);
+            print_frame(
+                &only_currency(report, "register.currency", currency)?,
+                precision,
+            );
+        }
+        Reporter::Unused { .. } => {
+            print_frame(&unused_accounts(&ledger, &context.opens)?, precision);
+        }
+        Reporter::Dormant { months, today, .. } => {
+            let today = today.unwrap_or_else(|| Local::today().naive_local());
+            let report = dormant_accounts(&ledger, months, today, &context.options)?;
+
+            print_frame(
+                &only_currency(report, "dormant.currency", currency)?,
+                precision,
+            );
+        }
+        Reporter::Unasserted { .. } => {
+            let report = unasserted_activity(&ledger, &context.balance_verifications)?;
+
+            print_frame(
+                &only_currency(report, "unasserted.currency", currency)?,
+                precision,
+            );
+        }
+    }
 
     Ok(())
 }
@@ -1,27 +1,174 @@
-use std::path::PathBuf;
+use std::{collections::BTreeSet, fs, path::PathBuf};
 
 use anyhow::Result;
+use chrono::NaiveDate;
 use structopt::StructOpt;
 
 #[derive(StructOpt)]
 pub struct Options {
     #[structopt(name = "file")]
     file: PathBuf,
+    /// How to print problems: human, json or sarif.
+    #[structopt(long, default_value = "human")]
+    format: OutputFormat,
+    /// Where to keep parsed ops between runs, so that months that didn't change are not parsed again.
+    /// Entries left from other ledgers are removed, so each ledger needs its own directory.
+    #[structopt(long)]
+    cache_dir: Option<PathBuf>,
+    /// Check the ledger op by op, so that memory stays bounded for very large files.
+    #[structopt(long, conflicts_with = "cache-dir")]
+    stream: bool,
+    /// Warn about ops dated after this day instead of after the current one, like `2021-03-10`.
+    #[structopt(long)]
+    today: Option<NaiveDate>,
+    /// Apply the fixes found for problems to the file, then check it again.
+    #[structopt(long)]
+    fix: bool,
 }
 
-use hortela::{compute_program, syntax, validate::ValidationRunner};
+use hortela::{
+    cache::Cache,
+    compute_program,
+    diagnostic::{emit, Diagnostic, OutputFormat},
+    fix::apply_fixes,
+    stream, syntax,
+    validate::{FutureDates, ValidationRunner},
+};
 
-fn main() -> Result<()> {
-    let options = Options::from_args();
-    let input = std::fs::read_to_string(&options.file)?;
+/// The ledger has no errors, although it may have warnings.
+const EXIT_OK: i32 = 0;
+/// The ledger has at least one error.
+const EXIT_INVALID: i32 = 1;
+/// The ledger could not be checked at all, like when the file can't be read.
+const EXIT_FAILURE: i32 = 2;
+
+fn future_dates(options: &Options) -> FutureDates {
+    options.today.map(FutureDates::new).unwrap_or_default()
+}
+
+fn check(options: &Options, input: &str) -> Result<Vec<Diagnostic>> {
+    let human = options.format == OutputFormat::Human;
 
-    let parsed = syntax::parse_string(&options.file, &input)?;
+    // Ops with syntax errors are left out, and the rest of the ledger is still checked.
+    let (parsed, mut diagnostics) = match &options.cache_dir {
+        Some(dir) => Cache::new(dir).parse(&options.file, input)?,
+        None => syntax::parse_string_recovery(&options.file, input),
+    };
     let (ledger, context) = compute_program(parsed)?;
 
-    println!("Validating transactions internal state...");
-    ValidationRunner::run_all(&options.file, &input, &ledger)?;
-    println!("Validating balance statements...");
-    ledger.validate_balances(context.balance_verifications)?;
+    let runner = ValidationRunner::default().replace(future_dates(options));
+
+    if human {
+        println!("Running {} validators...", runner.validators().count());
+    }
+    diagnostics.extend(runner.run(&options.file, &ledger, &context)?);
+
+    Ok(diagnostics)
+}
+
+/// Checks the ledger, returning its text along with the problems found, unless it was checked
+/// in stream mode, which never loads all of it.
+fn diagnose(options: &Options) -> Result<(Vec<Diagnostic>, Option<String>)> {
+    if options.stream {
+        if options.format == OutputFormat::Human {
+            println!("Validating the ledger op by op...");
+        }
+
+        let diagnostics = stream::verify_file(&options.file, future_dates(options).today)?;
+        Ok((diagnostics, None))
+    } else {
+        let input = fs::read_to_string(&options.file)?;
+        Ok((check(options, &input)?, Some(input)))
+    }
+}
+
+/// Writes the fixes of `diagnostics` to the file, returning whether it changed. Nothing is
+/// written when the fixed ledger has more syntax errors than the original one.
+fn fix(options: &Options, diagnostics: &[Diagnostic]) -> Result<bool> {
+    // Only read when there is something to fix, since the whole ledger is written back.
+    let input = fs::read_to_string(&options.file)?;
+    let (fixed, applied) = apply_fixes(&input, diagnostics);
+
+    if applied == 0 {
+        return Ok(false);
+    }
+
+    let syntax_errors = |text: &str| syntax::parse_string_recovery(&options.file, text).1.len();
+
+    if syntax_errors(&fixed) > syntax_errors(&input) {
+        eprintln!("Fixes were not applied, the fixed ledger would not parse");
+        return Ok(false);
+    }
+
+    // Written next to the ledger first, so that it is never left halfway written.
+    let mut temporary = options.file.clone().into_os_string();
+    temporary.push(".fix");
+    fs::write(&temporary, fixed)?;
+    fs::rename(&temporary, &options.file)?;
+
+    if options.format == OutputFormat::Human {
+        println!("Applied {} fix(es) to {}", applied, options.file.display());
+    }
+
+    Ok(true)
+}
+
+fn run(options: &Options) -> Result<bool> {
+    let (mut diagnostics, mut input) = diagnose(options)?;
+
+    // Fixes for later codes, like balance amounts, may depend on the ones for earlier codes, so
+    // each round only applies the fixes for one code before checking the ledger again.
+    let mut fixed = BTreeSet::new();
+
+    if options.fix {
+        loop {
+            let code = diagnostics
+                .iter()
+                .filter(|d| d.fix.is_some() && !fixed.contains(d.code))
+                .map(|d| d.code)
+                .min();
+
+            let code = match code {
+                Some(code) => code,
+                None => break,
+            };
+            fixed.insert(code);
+
+            let round = diagnostics
+                .iter()
+                .filter(|d| d.code == code)
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if fix(options, &round)? {
+                (diagnostics, input) = diagnose(options)?;
+            }
+        }
+    }
+
+    // In stream mode, only the lines the problems point at are read to show them.
+    match input {
+        Some(input) => emit(&diagnostics, input.as_str(), options.format),
+        None => {
+            let excerpt = stream::excerpt_file(&options.file, &diagnostics)?;
+            emit(&diagnostics, &excerpt, options.format);
+        }
+    }
+
+    Ok(!diagnostics.iter().any(Diagnostic::is_error))
+}
+
+fn main() {
+    let options = Options::from_args();
+
+    let code = match run(&options) {
+        Ok(true) => EXIT_OK,
+        Ok(false) => EXIT_INVALID,
+        Err(e) => {
+            eprintln!("Error: {:?}", e);
+            EXIT_FAILURE
+        }
+    };
 
-    Ok(())
+    std::process::exit(code);
 }
@@ -0,0 +1,326 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use chrono::{Datelike, Duration, NaiveDate};
+use num::ToPrimitive;
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::{is_same_or_child, Account},
+    ledger::Ledger,
+    money::Money,
+    options::{FiscalYearStart, LedgerOptions},
+    syntax::Span,
+};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Period {
+    Weekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl FromStr for Period {
+    type Err = String;
+
+    fn from_str(v: &str) -> std::result::Result<Self, Self::Err> {
+        match v {
+            "weekly" => Ok(Self::Weekly),
+            "monthly" => Ok(Self::Monthly),
+            "quarterly" => Ok(Self::Quarterly),
+            "yearly" => Ok(Self::Yearly),
+            _ => Err(format!("Unknown budget period `{}`", v)),
+        }
+    }
+}
+
+impl std::fmt::Display for Period {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Weekly => write!(f, "weekly"),
+            Self::Monthly => write!(f, "monthly"),
+            Self::Quarterly => write!(f, "quarterly"),
+            Self::Yearly => write!(f, "yearly"),
+        }
+    }
+}
+
+impl Period {
+    /// How many of this period fit in a year, used to convert budgets between periods.
+    pub fn per_year(&self) -> f64 {
+        match self {
+            Self::Weekly => 52.0,
+            Self::Monthly => 12.0,
+            Self::Quarterly => 4.0,
+            Self::Yearly => 1.0,
+        }
+    }
+
+    /// The first day of the period that contains `date`. Quarters and years follow the fiscal
+    /// year.
+    pub fn start_of(&self, date: NaiveDate, fiscal_year: &FiscalYearStart) -> NaiveDate {
+        match self {
+            Self::Weekly => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            Self::Monthly => NaiveDate::from_ymd(date.year(), date.month(), 1),
+            Self::Quarterly => {
+                let mut start = fiscal_year.year_of(date);
+
+                while self.next(start) <= date {
+                    start = self.next(start);
+                }
+
+                start
+            }
+            Self::Yearly => fiscal_year.year_of(date),
+        }
+    }
+
+    /// The first day of the period following the one that starts at `start`.
+    pub fn next(&self, start: NaiveDate) -> NaiveDate {
+        let add_months = |months: u32| {
+            let total = start.month0() + months;
+            NaiveDate::from_ymd(
+                start.year() + (total / 12) as i32,
+                total % 12 + 1,
+                start.day(),
+            )
+        };
+
+        match self {
+            Self::Weekly => start + Duration::weeks(1),
+            Self::Monthly => add_months(1),
+            Self::Quarterly => add_months(3),
+            Self::Yearly => add_months(12),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Budget {
+    pub account: Account,
+    pub amount: Money,
+    pub period: Period,
+    pub span: Span,
+}
+
+impl Budget {
+    pub fn new(account: Account, amount: Money, period: Period, span: Span) -> Self {
+        Self {
+            account,
+            amount,
+            period,
+            span,
+        }
+    }
+
+    /// The budgeted amount converted to a different reporting period.
+    pub fn amount_per(&self, period: Period) -> f64 {
+        self.amount.amount.to_f64().unwrap_or(0.0) * self.period.per_year() / period.per_year()
+    }
+}
+
+/// Every account name from `account` up to its root.
+fn self_and_parents(account: &Account) -> Vec<String> {
+    let parts = account.parts();
+
+    (1..=parts.len()).map(|n| parts[..n].join(":")).collect()
+}
+
+/// Compares budgeted and actual amounts for every `period` within the ledger's date range.
+///
+/// Actual amounts include all the children of a budgeted account, and parents of budgeted
+/// accounts get a rolled-up row with the sum of the budgets below them.
+pub fn budget_vs_actual(
+    ledger: &Ledger,
+    budgets: &[Budget],
+    period: Period,
+    options: &LedgerOptions,
+) -> Result<DataFrame> {
+    let df = ledger.all()?;
+
+    let dates = df
+        .column("ledger.date")?
+        .date()?
+        .as_date_iter()
+        .collect::<Vec<_>>();
+    let accounts = df.column("ledger.account_name")?.utf8()?;
+    let currencies = df.column("ledger.currency")?.utf8()?;
+    let amounts = df.column("ledger.signed_amount")?.f64()?;
+
+    let rows = dates
+        .into_iter()
+        .zip(accounts)
+        .zip(currencies)
+        .zip(amounts)
+        .filter_map(|(((date, acc), cur), amount)| Some((date?, acc?, cur?, amount?)))
+        .collect::<Vec<_>>();
+
+    let mut targets: BTreeMap<(String, String), f64> = BTreeMap::new();
+
+    for budget in budgets {
+        for name in self_and_parents(&budget.account) {
+            *targets
+                .entry((name, budget.amount.currency()))
+                .or_insert(0.0) += budget.amount_per(period);
+        }
+    }
+
+    let (first, last) = match (
+        rows.iter().map(|r| r.0).min(),
+        rows.iter().map(|r| r.0).max(),
+    ) {
+        (Some(first), Some(last)) => (first, last),
+        _ => (
+            NaiveDate::from_ymd(1970, 1, 1),
+            NaiveDate::from_ymd(1970, 1, 1),
+        ),
+    };
+
+    let mut period_col = vec![];
+    let mut account_col = vec![];
+    let mut currency_col = vec![];
+    let mut budget_col = vec![];
+    let mut actual_col = vec![];
+
+    let mut start = period.start_of(first, &options.fiscal_year_start);
+
+    while start <= last || period_col.is_empty() {
+        let end = period.next(start);
+
+        for ((account, currency), budgeted) in targets.iter() {
+            let actual: f64 = rows
+                .iter()
+                .filter(|(date, acc, cur, _)| {
+                    *date >= start
+                        && *date < end
+                        && cur == currency
+                        && is_same_or_child(acc, account)
+                })
+                .fold(0.0, |sum, r| sum + r.3);
+
+            period_col.push(start);
+            account_col.push(account.clone());
+            currency_col.push(currency.clone());
+            budget_col.push(*budgeted);
+            actual_col.push(actual);
+        }
+
+        start = end;
+    }
+
+    let remaining = budget_col
+        .iter()
+        .zip(actual_col.iter())
+        .map(|(b, a)| b - a)
+        .collect::<Vec<_>>();
+    let percentage = budget_col
+        .iter()
+        .zip(actual_col.iter())
+        .map(|(b, a)| if *b == 0.0 { None } else { Some(a / b * 100.0) })
+        .collect::<Vec<_>>();
+
+    DataFrame::new(vec![
+        DateChunked::new_from_naive_date("budget.period", &period_col).into_series(),
+        Series::new("budget.account_name", account_col),
+        Series::new("budget.currency", currency_col),
+        Series::new("budget.budget", budget_col),
+        Series::new("budget.actual", actual_col),
+        Series::new("budget.remaining", remaining),
+        Series::new("budget.percentage", percentage),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_period_boundaries() {
+        let date = NaiveDate::from_ymd(2021, 11, 17);
+        let calendar = FiscalYearStart::default();
+
+        assert_eq!(
+            Period::Weekly.start_of(date, &calendar),
+            NaiveDate::from_ymd(2021, 11, 15)
+        );
+        assert_eq!(
+            Period::Monthly.start_of(date, &calendar),
+            NaiveDate::from_ymd(2021, 11, 1)
+        );
+        assert_eq!(
+            Period::Quarterly.start_of(date, &calendar),
+            NaiveDate::from_ymd(2021, 10, 1)
+        );
+        assert_eq!(
+            Period::Yearly.start_of(date, &calendar),
+            NaiveDate::from_ymd(2021, 1, 1)
+        );
+
+        assert_eq!(
+            Period::Monthly.next(NaiveDate::from_ymd(2021, 12, 1)),
+            NaiveDate::from_ymd(2022, 1, 1)
+        );
+        assert_eq!(
+            Period::Quarterly.next(NaiveDate::from_ymd(2021, 10, 1)),
+            NaiveDate::from_ymd(2022, 1, 1)
+        );
+    }
+
+    #[test]
+    fn test_fiscal_periods() {
+        let date = NaiveDate::from_ymd(2021, 3, 17);
+        let fiscal = FiscalYearStart { month: 4, day: 1 };
+
+        assert_eq!(
+            Period::Quarterly.start_of(date, &fiscal),
+            NaiveDate::from_ymd(2021, 1, 1)
+        );
+        assert_eq!(
+            Period::Yearly.start_of(date, &fiscal),
+            NaiveDate::from_ymd(2020, 4, 1)
+        );
+        assert_eq!(
+            Period::Yearly.next(NaiveDate::from_ymd(2020, 4, 1)),
+            NaiveDate::from_ymd(2021, 4, 1)
+        );
+    }
+
+    #[test]
+    fn test_budget_vs_actual() -> anyhow::Result<()> {
+        let input = r#"
+budget expenses:food 1500 BRL monthly
+budget expenses:home 500 BRL monthly
+
+2020-01-05 transaction "Market"
+  > 400 BRL assets:cash
+  < 400 BRL expenses:food:market
+"#;
+
+        let program = crate::syntax::parse_string(std::path::Path::new("test.hta"), input)?;
+        let (ledger, context) = crate::compute_program(program)?;
+        let df = budget_vs_actual(&ledger, &context.budgets, Period::Monthly, &context.options)?;
+
+        let accounts = df.column("budget.account_name")?.utf8()?;
+        let budgets = df.column("budget.budget")?.f64()?;
+        let actuals = df.column("budget.actual")?.f64()?;
+
+        let rows = accounts
+            .into_iter()
+            .zip(budgets.into_iter())
+            .zip(actuals.into_iter())
+            .map(|((a, b), c)| (a.unwrap().to_string(), b.unwrap(), c.unwrap()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            rows,
+            vec![
+                ("expenses".to_string(), 2000.0, 400.0),
+                ("expenses:food".to_string(), 1500.0, 400.0),
+                ("expenses:home".to_string(), 500.0, 0.0),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+//! An on-disk cache of parsed ops, so that the parts of a ledger that didn't change are not
+//! parsed again.
+//!
+//! Ledgers are split into chunks of the entries dated in the same month, and each chunk is keyed
+//! by a SHA-256 hash of its text, where it starts, the aliases of the whole ledger and
+//! [`FORMAT`]. Editing the current month so only invalidates its own chunk, and the ones after it
+//! when lines are added or removed, as their spans move. Chunks with syntax errors are never
+//! cached, and any entry that can't be read back, or whose stored hash doesn't match its key, is
+//! treated as missing. Entries the last run didn't use are removed, so a cache directory holds a
+//! single ledger and doesn't grow as it is edited.
+
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    diagnostic::{Diagnostic, Diagnostics},
+    stream::{keyword, Entries},
+    syntax::{parse_op, Aliases, Op, Spanned},
+};
+
+/// The version of the grammar and of the way ops are stored. It must be bumped whenever either
+/// changes, so that chunks cached before are parsed again.
+pub const FORMAT: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    format: u32,
+    hash: String,
+    ops: Vec<Spanned<Op>>,
+}
+
+/// Consecutive entries of a ledger, along with the offset where each starts.
+type Chunk = Vec<(String, usize)>;
+
+/// The year and month an entry is dated in, or `None` for ops without a date.
+fn month(text: &str) -> Option<(i32, u32)> {
+    let date = NaiveDate::parse_from_str(text.get(..10)?, "%Y-%m-%d").ok()?;
+
+    Some((date.year(), date.month()))
+}
+
+/// Splits `input` into one chunk per month. Entries without a date stay with the ones around them.
+fn chunks(input: &str) -> Result<Vec<Chunk>> {
+    let mut chunks: Vec<Chunk> = vec![];
+    let mut current = None;
+
+    for entry in Entries::new(input.as_bytes()) {
+        let entry = entry?;
+        let month = month(&entry.0);
+
+        match chunks.last_mut() {
+            Some(chunk) if month.is_none() || current.is_none() || month == current => {
+                chunk.push(entry)
+            }
+            _ => chunks.push(vec![entry]),
+        }
+
+        current = month.or(current);
+    }
+
+    Ok(chunks)
+}
+
+fn key(aliases: &BTreeSet<String>, chunk: &[(String, usize)]) -> String {
+    let mut hasher = Sha256::new();
+
+    hasher.update(FORMAT.to_le_bytes());
+    hasher.update((aliases.len() as u64).to_le_bytes());
+    for alias in aliases {
+        hasher.update((alias.len() as u64).to_le_bytes());
+        hasher.update(alias.as_bytes());
+    }
+    for (text, offset) in chunk {
+        hasher.update((*offset as u64).to_le_bytes());
+        hasher.update((text.len() as u64).to_le_bytes());
+        hasher.update(text.as_bytes());
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// The ops of the chunk with `key`, from the last time it was cached.
+    fn get(&self, key: &str) -> Option<Vec<Spanned<Op>>> {
+        let contents = fs::read_to_string(self.path(key)).ok()?;
+        let entry: Entry = serde_json::from_str(&contents).ok()?;
+
+        if entry.format != FORMAT || entry.hash != key {
+            return None;
+        }
+
+        Some(entry.ops)
+    }
+
+    fn put(&self, key: &str, ops: &[Spanned<Op>]) -> Result<()> {
+        let entry = Entry {
+            format: FORMAT,
+            hash: key.to_string(),
+            ops: ops.to_vec(),
+        };
+
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.path(key), serde_json::to_string(&entry)?)?;
+
+        Ok(())
+    }
+
+    /// Removes the entries whose key is not in `used`. Other files in the directory are kept.
+    fn prune(&self, used: &BTreeSet<String>) -> Result<()> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        for entry in entries {
+            let path = entry?.path();
+            let key = match path.file_name().and_then(|n| n.to_str()) {
+                Some(name) => name.strip_suffix(".json"),
+                None => None,
+            };
+
+            match key {
+                Some(key)
+                    if key.len() == 64
+                        && key.bytes().all(|b| b.is_ascii_hexdigit())
+                        && !used.contains(key) =>
+                {
+                    fs::remove_file(&path)?
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Same as [`parse_string_recovery`](crate::syntax::parse_string_recovery), but reuses the
+    /// ops of every chunk that was parsed before, and stores the others.
+    pub fn parse(
+        &self,
+        filename: &Path,
+        input: &str,
+    ) -> Result<(Vec<Spanned<Op>>, Vec<Diagnostic>)> {
+        let chunks = chunks(input)?;
+
+        // Aliases can be used before they are defined, so they are all found first.
+        let mut aliases = Aliases::new();
+        for (text, offset) in chunks.iter().flatten() {
+            if keyword(text).as_deref() != Some("alias") {
+                continue;
+            }
+
+            if let Ok((Op::Alias((name, _), (account, _)), _)) =
+                parse_op(filename, text, *offset, &Aliases::new())
+            {
+                aliases.insert(name, account);
+            }
+        }
+        let written = aliases
+            .iter()
+            .map(|(name, account)| format!("{} {}", name, account))
+            .collect::<BTreeSet<_>>();
+
+        let mut ops = vec![];
+        let mut diagnostics = vec![];
+        let mut used = BTreeSet::new();
+
+        for chunk in chunks {
+            let key = key(&written, &chunk);
+            used.insert(key.clone());
+
+            if let Some(cached) = self.get(&key) {
+                ops.extend(cached);
+                continue;
+            }
+
+            let mut parsed = vec![];
+            let mut errors = vec![];
+
+            for (text, offset) in &chunk {
+                match parse_op(filename, text, *offset, &aliases) {
+                    Ok(op) => parsed.push(op),
+                    Err(Diagnostics(found)) => errors.extend(found),
+                }
+            }
+
+            if errors.is_empty() {
+                self.put(&key, &parsed)?;
+            }
+
+            ops.extend(parsed);
+            diagnostics.extend(errors);
+        }
+
+        self.prune(&used)?;
+
+        Ok((ops, diagnostics))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::parse_string_recovery;
+
+    const INPUT: &str = r#"alias cash = assets:cash
+2020-01-01 open assets:cash BRL
+2020-01-02 transaction "Initial import"
+  > 100.5 BRL equity:initial_import
+  < 100.5 BRL cash
+
+// February
+2020-02-03 transaction "Market"
+  > 20 BRL cash
+  < 20 BRL expenses:food
+"#;
+
+    fn cache_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hortela-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn keys(input: &str) -> Result<Vec<String>> {
+        let aliases = ["cash assets:cash".to_string()].into_iter().collect();
+
+        Ok(chunks(input)?
+            .iter()
+            .map(|chunk| key(&aliases, chunk))
+            .collect())
+    }
+
+    #[test]
+    fn test_cache_round_trip() -> Result<()> {
+        let cache = Cache::new(cache_dir("round-trip"));
+        let filename = Path::new("books.hta");
+
+        let (parsed, diagnostics) = cache.parse(filename, INPUT)?;
+        assert!(diagnostics.is_empty());
+        assert_eq!(parsed, parse_string_recovery(filename, INPUT).0);
+
+        let keys = keys(INPUT)?;
+        assert_eq!(keys.len(), 2);
+        assert_eq!(cache.get(&keys[0]), Some(parsed[..3].to_vec()));
+        assert_eq!(cache.get(&keys[1]), Some(parsed[3..].to_vec()));
+
+        assert_eq!(cache.parse(filename, INPUT)?.0, parsed);
+
+        fs::remove_dir_all(&cache.dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_reuses_unchanged_months() -> Result<()> {
+        let cache = Cache::new(cache_dir("months"));
+        let filename = Path::new("books.hta");
+        let edited = INPUT.replace("20 BRL", "25 BRL");
+
+        cache.parse(filename, INPUT)?;
+
+        // January is read back from the cache, so what it holds is what comes out.
+        let january = keys(&edited)?.remove(0);
+        assert_eq!(january, keys(INPUT)?[0]);
+        cache.put(&january, &[])?;
+
+        let (parsed, diagnostics) = cache.parse(filename, &edited)?;
+        assert!(diagnostics.is_empty());
+        assert_eq!(parsed, parse_string_recovery(filename, &edited).0[3..]);
+
+        fs::remove_dir_all(&cache.dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_checks_entries() -> Result<()> {
+        let cache = Cache::new(cache_dir("checks"));
+        let key = keys(INPUT)?.remove(0);
+        let write = |format: u32, hash: &str| {
+            let entry = Entry {
+                format,
+                hash: hash.to_string(),
+                ops: vec![],
+            };
+            fs::write(cache.path(&key), serde_json::to_string(&entry)?)
+        };
+
+        fs::create_dir_all(&cache.dir)?;
+
+        write(FORMAT, &key)?;
+        assert_eq!(cache.get(&key), Some(vec![]));
+
+        write(FORMAT + 1, &key)?;
+        assert_eq!(cache.get(&key), None);
+
+        write(FORMAT, &"0".repeat(64))?;
+        assert_eq!(cache.get(&key), None);
+
+        fs::write(cache.path(&key), "{")?;
+        assert_eq!(cache.get(&key), None);
+
+        fs::remove_dir_all(&cache.dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_prunes_unused_entries() -> Result<()> {
+        let cache = Cache::new(cache_dir("prune"));
+        let filename = Path::new("books.hta");
+        let edited = INPUT.replace("20 BRL", "25 BRL");
+
+        cache.parse(filename, INPUT)?;
+        fs::write(cache.dir.join("notes.txt"), "")?;
+        cache.parse(filename, &edited)?;
+
+        let mut files = fs::read_dir(&cache.dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<io::Result<Vec<_>>>()?;
+        files.sort();
+
+        let mut expected = keys(&edited)?
+            .iter()
+            .map(|key| format!("{}.json", key))
+            .collect::<Vec<_>>();
+        expected.push("notes.txt".to_string());
+        expected.sort();
+
+        // February was replaced by its edited version, and January was kept.
+        assert_eq!(files, expected);
+        assert!(cache.get(&keys(INPUT)?[1]).is_none());
+
+        fs::remove_dir_all(&cache.dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_skips_syntax_errors() -> Result<()> {
+        let cache = Cache::new(cache_dir("errors"));
+        let input = INPUT.replace("open", "opne");
+
+        let (parsed, diagnostics) = cache.parse(Path::new("books.hta"), &input)?;
+        let keys = keys(&input)?;
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(parsed.len(), 3);
+        assert!(cache.get(&keys[0]).is_none());
+        assert!(cache.get(&keys[1]).is_some());
+
+        fs::remove_dir_all(&cache.dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+use chrono::NaiveDate;
+use polars::prelude::*;
+
+use crate::{
+    account::Account,
+    money::Currency,
+    syntax::{Metadata, Span},
+};
+
+#[derive(Debug, Clone)]
+pub struct AccountOpening {
+    pub date: NaiveDate,
+    pub account: Account,
+    pub currencies: Vec<Currency>,
+    pub metadata: Metadata,
+    pub span: Span,
+}
+
+impl AccountOpening {
+    pub fn new(
+        date: NaiveDate,
+        account: Account,
+        currencies: Vec<Currency>,
+        metadata: Metadata,
+        span: Span,
+    ) -> Self {
+        Self {
+            date,
+            account,
+            currencies,
+            metadata,
+            span,
+        }
+    }
+
+    /// An account opened without currencies accepts movements in any of them.
+    pub fn allows(&self, currency: &Currency) -> bool {
+        self.currencies.is_empty() || self.currencies.contains(currency)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn institution(&self) -> Option<&str> {
+        self.get("institution")
+    }
+
+    pub fn number(&self) -> Option<&str> {
+        self.get("number")
+    }
+
+    pub fn note(&self) -> Option<&str> {
+        self.get("note")
+    }
+}
+
+/// Finds the `open` directive for an account, if there is one.
+pub fn find_opening<'a>(opens: &'a [AccountOpening], account: &str) -> Option<&'a AccountOpening> {
+    opens.iter().find(|o| o.account.to_string() == account)
+}
+
+/// Lists every opened account, sorted by name, along with its allowed currencies and metadata.
+pub fn chart_of_accounts(opens: &[AccountOpening]) -> Result<DataFrame> {
+    let mut opens = opens.iter().collect::<Vec<_>>();
+    opens.sort_by_key(|o| o.account.to_string());
+
+    DataFrame::new(vec![
+        Series::new(
+            "chart.account_name",
+            opens
+                .iter()
+                .map(|o| o.account.to_string())
+                .collect::<Vec<_>>(),
+        ),
+        DateChunked::new_from_naive_date(
+            "chart.opened_on",
+            &opens.iter().map(|o| o.date).collect::<Vec<_>>(),
+        )
+        .into_series(),
+        Series::new(
+            "chart.currencies",
+            opens
+                .iter()
+                .map(|o| {
+                    o.currencies
+                        .iter()
+                        .map(|c| c.0.clone())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "chart.institution",
+            opens
+                .iter()
+                .map(|o| o.institution().map(String::from))
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "chart.number",
+            opens
+                .iter()
+                .map(|o| o.number().map(String::from))
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "chart.note",
+            opens
+                .iter()
+                .map(|o| o.note().map(String::from))
+                .collect::<Vec<_>>(),
+        ),
+    ])
+}
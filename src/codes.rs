@@ -0,0 +1,490 @@
+//! Stable codes for every kind of diagnostic, along with a long-form explanation of each.
+//!
+//! Codes never change meaning once released. They are grouped by where the problem is found:
+//!
+//! | Range   | Found while                    |
+//! |---------|--------------------------------|
+//! | `H01xx` | parsing the ledger             |
+//! | `H02xx` | validating transactions        |
+//! | `H03xx` | checking `balance` directives  |
+//!
+//! Run `hortela-explain <code>` to read the explanation of a code.
+
+/// The parser found a token that can't appear at that point.
+pub const UNEXPECTED_TOKEN: &str = "H0101";
+/// A delimiter was opened but never closed.
+pub const UNCLOSED_DELIMITER: &str = "H0102";
+/// The tokens are in the right place, but their value is not valid.
+pub const INVALID_SYNTAX: &str = "H0103";
+/// A string was opened but never closed.
+pub const UNTERMINATED_STRING: &str = "H0104";
+/// Credits and debits of the whole ledger do not add up to the same amount.
+pub const UNBALANCED_LEDGER: &str = "H0201";
+/// Credits and debits of a single transaction do not add up to the same amount.
+pub const UNBALANCED_TRANSACTION: &str = "H0202";
+/// A movement uses a currency its account was not opened with.
+pub const CURRENCY_NOT_ALLOWED: &str = "H0203";
+/// A `document` directive points to a file that does not exist.
+pub const MISSING_DOCUMENT: &str = "H0204";
+/// A transaction looks like another one written before it.
+pub const DUPLICATE_TRANSACTION: &str = "H0205";
+/// An op is dated before the op written right before it.
+pub const OUT_OF_ORDER: &str = "H0206";
+/// An op is dated after the current day.
+pub const FUTURE_DATE: &str = "H0207";
+/// The balance of an account goes to the opposite of its normal side.
+pub const ABNORMAL_BALANCE: &str = "H0208";
+/// An account was opened but no movement uses it.
+pub const UNUSED_ACCOUNT: &str = "H0209";
+/// An account name breaks the naming rules set by the ledger's options.
+pub const ACCOUNT_NAMING: &str = "H0210";
+/// A transaction has a single movement.
+pub const SINGLE_MOVEMENT: &str = "H0211";
+/// A movement has an amount of zero.
+pub const ZERO_AMOUNT: &str = "H0212";
+/// Every movement of a transaction is a debit, or every one is a credit.
+pub const ONE_SIDED_TRANSACTION: &str = "H0213";
+/// A movement uses an account that no `open` directive opens.
+pub const UNOPENED_ACCOUNT: &str = "H0214";
+/// A `rule.*` option names a rule that no validator uses.
+pub const UNKNOWN_RULE: &str = "H0215";
+/// A `balance` directive does not match the balance computed from the ledger.
+pub const BALANCE_MISMATCH: &str = "H0301";
+/// An `assert` op does not hold for the ledger.
+pub const ASSERTION_FAILED: &str = "H0302";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodeInfo {
+    pub code: &'static str,
+    pub title: &'static str,
+    pub explanation: &'static str,
+}
+
+pub static ALL_CODES: &[CodeInfo] = &[
+    CodeInfo {
+        code: UNEXPECTED_TOKEN,
+        title: "Unexpected token",
+        explanation: r#"The parser found a token that can't appear at that point of the ledger.
+
+This usually means a keyword is misspelled, or an op is missing one of its parts.
+
+Example:
+
+    2021-01-01 opne assets:cash BRL
+
+Fix it by checking the op against its expected form, here `<date> open <account> <currencies>`:
+
+    2021-01-01 open assets:cash BRL
+"#,
+    },
+    CodeInfo {
+        code: UNCLOSED_DELIMITER,
+        title: "Unclosed delimiter",
+        explanation: r#"A delimiter was opened but the input ended, or another op started, before it was closed.
+
+Strings that are never closed have their own code, `H0104`.
+"#,
+    },
+    CodeInfo {
+        code: INVALID_SYNTAX,
+        title: "Invalid value",
+        explanation: r#"The op has the right shape, but one of its values is not valid: a date that does not
+exist, an account or alias that was never defined, an unknown budget period, an option with
+an invalid value or an unknown escape inside a string.
+
+Example:
+
+    2021-02-30 open assets:cash BRL
+    option "display_precision" "two"
+
+Fix it by using a valid value:
+
+    2021-02-28 open assets:cash BRL
+    option "display_precision" "2"
+
+The escapes allowed inside strings are `\\`, `\"`, `\n` and unicode code points like `\u{e9}`.
+"#,
+    },
+    CodeInfo {
+        code: UNTERMINATED_STRING,
+        title: "Unterminated string",
+        explanation: r#"A string was opened with `"` but the line ended before it was closed, or it was opened
+with `"""` and the file ended before it was closed.
+
+Strings opened with a single `"` can't span lines. Use `\n` for a line break, or triple
+quotes for text with several lines.
+
+Example:
+
+    2021-01-01 transaction "Groceries
+      > 50 BRL assets:cash
+      < 50 BRL expenses:food
+
+Fix it by closing the string:
+
+    2021-01-01 transaction "Groceries"
+"#,
+    },
+    CodeInfo {
+        code: UNBALANCED_LEDGER,
+        title: "Ledger does not balance",
+        explanation: r#"In double-entry accounting every credit has a matching debit, so the sum of all credits
+in the ledger must equal the sum of all debits, in each currency and on every day.
+
+Sums are exact, and each currency is checked on its own. The diagnostic points at the first
+transaction on the first day the books stop balancing, which is usually also reported with
+`H0202`.
+
+Fix it by balancing every transaction in the ledger.
+"#,
+    },
+    CodeInfo {
+        code: UNBALANCED_TRANSACTION,
+        title: "Transaction does not balance",
+        explanation: r#"The credits (`>`) and debits (`<`) of a transaction must add up to exactly the same amount,
+or within the `default_tolerance` option when the ledger sets one.
+
+Example:
+
+    2021-01-01 transaction "Groceries"
+      > 50 BRL assets:cash
+      < 45 BRL expenses:food
+
+Fix it by adding the missing movement or correcting an amount:
+
+    2021-01-01 transaction "Groceries"
+      > 50 BRL assets:cash
+      < 45 BRL expenses:food
+      <  5 BRL expenses:cleaning
+"#,
+    },
+    CodeInfo {
+        code: CURRENCY_NOT_ALLOWED,
+        title: "Currency not allowed for account",
+        explanation: r#"An account opened with a list of currencies only accepts movements in those currencies.
+
+Example:
+
+    2021-01-01 open assets:cash BRL
+    2021-01-02 transaction "Exchange"
+      > 10 USD equity:initial_import
+      < 10 USD assets:cash
+
+Fix it by moving the amount to an account that accepts the currency, or by adding the currency
+to the `open` directive:
+
+    2021-01-01 open assets:cash BRL USD
+"#,
+    },
+    CodeInfo {
+        code: MISSING_DOCUMENT,
+        title: "Document does not exist",
+        explanation: r#"A `document` directive points to a file that does not exist. Paths are relative to the
+directory of the ledger file.
+
+Example:
+
+    2021-01-31 document assets:bank "statements/2021-01.pdf"
+
+Fix it by correcting the path, or by adding the missing file.
+"#,
+    },
+    CodeInfo {
+        code: DUPLICATE_TRANSACTION,
+        title: "Possible duplicate transaction",
+        explanation: r#"Two transactions move the same amounts between the same accounts, on the same day or within
+the `duplicate_window` option in days, and have similar descriptions. This usually means a
+statement was imported twice.
+
+Example:
+
+    2021-01-05 transaction "Market"
+      > 50 BRL assets:cash
+      < 50 BRL expenses:food
+    2021-01-05 transaction "MARKET"
+      > 50 BRL assets:cash
+      < 50 BRL expenses:food
+
+Fix it by removing one of them. When the repetition is intentional, add a `repeated` key to
+either transaction to say why:
+
+    2021-01-05 transaction "Market"
+      repeated: "Paid for two separate orders"
+      > 50 BRL assets:cash
+      < 50 BRL expenses:food
+"#,
+    },
+    CodeInfo {
+        code: OUT_OF_ORDER,
+        title: "Op out of date order",
+        explanation: r#"Ops are expected to be written in date order, and this one is dated before the op right
+above it. This is almost always a typo in the year or month.
+
+Example:
+
+    2021-03-01 transaction "Rent"
+      > 1000 BRL assets:bank
+      < 1000 BRL expenses:rent
+    2012-03-04 transaction "Market"
+      > 50 BRL assets:bank
+      < 50 BRL expenses:food
+
+Fix it by correcting the date, or by moving the op to where its date belongs. This is a warning
+unless the ledger sets `option "strict_date_order" "true"`.
+"#,
+    },
+    CodeInfo {
+        code: FUTURE_DATE,
+        title: "Op dated in the future",
+        explanation: r#"The op is dated after the current day, or after the day given to `hortela-verify --today`.
+Entries are written once things happen, so this is usually a typo in the date.
+
+Example, checked on 2021-03-10:
+
+    2021-03-14 transaction "Market"
+      > 50 BRL assets:bank
+      < 50 BRL expenses:food
+
+Fix it by correcting the date. Scheduled entries can be kept in another file until they happen.
+"#,
+    },
+    CodeInfo {
+        code: ABNORMAL_BALANCE,
+        title: "Account balance on its abnormal side",
+        explanation: r#"Each type of account has a normal side: assets and expenses normally have a debit balance,
+while liabilities, income and equity normally have a credit balance. At the end of this day the
+balance of the account went to the other side, like an expense with a net credit or cash going
+negative. This usually means `<` and `>` were swapped in a movement.
+
+Example:
+
+    2021-01-05 transaction "Market"
+      < 50 BRL assets:cash
+      > 50 BRL expenses:food
+
+Fix it by swapping the sides of the movements:
+
+    2021-01-05 transaction "Market"
+      > 50 BRL assets:cash
+      < 50 BRL expenses:food
+
+When an account can go to either side, like a checking account with an overdraft, add an
+`abnormal_balance` key to its `open` directive. It also applies to the accounts below it:
+
+    2021-01-01 open assets:bank:checking BRL
+      abnormal_balance: "Overdraft up to 500 BRL"
+"#,
+    },
+    CodeInfo {
+        code: UNUSED_ACCOUNT,
+        title: "Account opened but never used",
+        explanation: r#"An `open` directive declares an account, but no movement uses it or any account below it.
+This usually means the account was renamed in the movements, or was left behind when they were
+moved to another account.
+
+Example:
+
+    2021-01-01 open assets:bank:checking BRL
+    2021-01-01 open assets:bank:cheking BRL
+
+Fix it by removing the `open` directive, or by correcting the movements that were meant to use
+the account. `hortela-report unused` lists every unused account.
+"#,
+    },
+    CodeInfo {
+        code: ACCOUNT_NAMING,
+        title: "Account name breaks the naming rules",
+        explanation: r#"Ledgers can restrict how accounts are named, which keeps a chart of accounts shared by
+several people consistent:
+
+    option "account_categories.expenses" "food home transport"
+    option "account_pattern.assets" "^assets:(bank|cash|investments)(:[a-z_]+)*$"
+    option "max_account_depth" "4"
+
+`account_categories.<root>` lists the names allowed right below a root account,
+`account_pattern.<root>` is a regular expression that full account names under the root must
+match, and `max_account_depth` limits how many parts a name has, counting the root. Each account
+is reported once, where it is first written.
+
+Example:
+
+    2021-01-05 transaction "Market"
+      > 50 BRL assets:bank
+      < 50 BRL expenses:groceries
+
+Fix it by using one of the allowed names, like `expenses:food`, or by changing the options if
+the chart of accounts changed on purpose.
+"#,
+    },
+    CodeInfo {
+        code: SINGLE_MOVEMENT,
+        title: "Transaction with a single movement",
+        explanation: r#"A transaction moves money from some accounts to others, so it needs at least two movements:
+one credit (`>`) and one debit (`<`). This one has a single movement, which usually means the
+other side was forgotten.
+
+Example:
+
+    2021-01-05 transaction "Market"
+      < 50 BRL expenses:food
+
+Fix it by adding the account the money came from:
+
+    2021-01-05 transaction "Market"
+      > 50 BRL assets:cash
+      < 50 BRL expenses:food
+"#,
+    },
+    CodeInfo {
+        code: ZERO_AMOUNT,
+        title: "Movement with a zero amount",
+        explanation: r#"A movement of zero doesn't change any balance. It is usually a typo, like a missing digit or a
+value that was never filled in after an import.
+
+Example:
+
+    2021-01-05 transaction "Market"
+      > 0 BRL assets:cash
+      < 0 BRL expenses:food
+
+Fix it by writing the right amount, or by removing the movement.
+"#,
+    },
+    CodeInfo {
+        code: ONE_SIDED_TRANSACTION,
+        title: "Transaction with movements on one side only",
+        explanation: r#"Every movement of the transaction is a debit (`<`), or every one is a credit (`>`), so money
+is not coming from anywhere, or not going anywhere. This usually means the sign of one of the
+movements is wrong.
+
+Example:
+
+    2021-01-05 transaction "Market"
+      < 50 BRL assets:cash
+      < 50 BRL expenses:food
+
+Fix it by turning the movement the money comes from into a credit:
+
+    2021-01-05 transaction "Market"
+      > 50 BRL assets:cash
+      < 50 BRL expenses:food
+"#,
+    },
+    CodeInfo {
+        code: UNOPENED_ACCOUNT,
+        title: "Account used without being opened",
+        explanation: r#"A movement uses an account that is not opened by any `open` directive, neither itself nor an
+account above it. This is often a typo in the account name, which would otherwise create a new
+account silently.
+
+Example:
+
+    2021-01-01 open assets:cash BRL
+    2021-01-01 open expenses:food
+    2021-01-05 transaction "Market"
+      > 50 BRL assets:cash
+      < 50 BRL expenses:fod
+
+Fix it by correcting the name, or by opening the account if it is a new one:
+
+    2021-01-05 open expenses:fod
+"#,
+    },
+    CodeInfo {
+        code: UNKNOWN_RULE,
+        title: "Unknown rule",
+        explanation: r#"A `rule.*` option turns off or changes the severity of a rule that no validator uses, so it
+has no effect. This is often a typo in the rule name, which would otherwise leave the rule
+running as before.
+
+Example:
+
+    option "rule.duplicate_transactions" "off"
+
+Fix it by using the name of the validator, as listed in the note of the diagnostic:
+
+    option "rule.duplicate_transaction" "off"
+"#,
+    },
+    CodeInfo {
+        code: BALANCE_MISMATCH,
+        title: "Balance does not match",
+        explanation: r#"A `balance` directive asserts how much an account holds in a currency at the end of a day.
+The amount computed from every transaction up to that day is different when both are rounded to
+the `display_precision` option, or beyond the `default_tolerance` option when the ledger sets
+one.
+
+Example:
+
+    2021-01-01 transaction "Salary"
+      > 1000 BRL income:salary
+      < 1000 BRL assets:bank
+    2021-01-02 balance assets:bank 1200 BRL
+
+Fix it by finding the missing or wrong transaction, or by correcting the asserted amount:
+
+    2021-01-02 balance assets:bank 1000 BRL
+"#,
+    },
+    CodeInfo {
+        code: ASSERTION_FAILED,
+        title: "Assertion does not hold",
+        explanation: r#"An `assert` op compares a query over the ledger with an amount or a number, and the
+comparison is false. Queries are `sum(<account>, <year or month>)`, `balance(<account>)` and
+`count(tag:<key>, <value>)`, and only look at entries up to the date of the assertion when it
+has one. Accounts match the ones below them too, or only those when written like
+`assets:bank:*`. Values are equal the same way amounts of `balance` directives are.
+
+Example:
+
+    2021-03-02 transaction "Market"
+      > 300 BRL assets:bank
+      < 300 BRL expenses:food
+    assert 2021-12-31 sum(expenses:food, 2021) < 200 BRL
+
+Fix it by correcting the transactions the query looks at, or by updating the assertion when
+it no longer reflects what you expect:
+
+    assert 2021-12-31 sum(expenses:food, 2021) < 500 BRL
+"#,
+    },
+];
+
+/// Finds the information of `code`, ignoring case.
+pub fn explain(code: &str) -> Option<&'static CodeInfo> {
+    ALL_CODES.iter().find(|c| c.code.eq_ignore_ascii_case(code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_unique() {
+        let mut codes = ALL_CODES.iter().map(|c| c.code).collect::<Vec<_>>();
+        codes.sort_unstable();
+        codes.dedup();
+
+        assert_eq!(codes.len(), ALL_CODES.len());
+    }
+
+    #[test]
+    fn test_explain() {
+        assert_eq!(
+            explain("h0202").map(|c| c.code),
+            Some(UNBALANCED_TRANSACTION)
+        );
+        assert!(explain("H9999").is_none());
+    }
+
+    #[test]
+    fn test_validator_codes_are_explained() {
+        for validator in crate::validate::ValidationRunner::default().validators() {
+            assert!(
+                explain(validator.code()).is_some(),
+                "`{}` has no explanation",
+                validator.name()
+            );
+        }
+    }
+}
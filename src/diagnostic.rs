@@ -0,0 +1,646 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use ariadne::{Color, Label as ReportLabel, Report, ReportKind, Source};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{codes::explain, syntax::Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+            Self::Note => write!(f, "note"),
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "error" => Ok(Self::Error),
+            "warning" => Ok(Self::Warning),
+            "note" => Ok(Self::Note),
+            _ => Err(format!(
+                "Unknown severity `{}`, expected error, warning or note",
+                value
+            )),
+        }
+    }
+}
+
+/// A message attached to a specific part of the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A change to the source, part of a [`Fix`].
+///
+/// Spans are the same ones diagnostics point to, so they may include whitespace and comments
+/// around the text they are about. Those are always left as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextEdit {
+    /// Replaces the text in the span.
+    Replace(Span, String),
+    /// Adds lines right after the line where the text in the span ends. The text ends with a
+    /// line break, like every line.
+    AddLines(Span, String),
+}
+
+/// Edits that solve the problem reported by a diagnostic, applied together or not at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fix {
+    /// What the fix does, like "Add the missing movement".
+    pub message: String,
+    pub edits: Vec<TextEdit>,
+}
+
+impl Fix {
+    pub fn new<M: Into<String>>(message: M, edits: Vec<TextEdit>) -> Self {
+        Self {
+            message: message.into(),
+            edits,
+        }
+    }
+}
+
+/// A problem found while parsing or validating a ledger.
+///
+/// Diagnostics are plain data: the library never prints them, so that the command line tools
+/// and editor integrations can each present them in their own way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// One of the stable codes in [`crate::codes`], like `H0101`.
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub file: PathBuf,
+    /// The part of the source the diagnostic is about, if it is about a specific part.
+    pub span: Option<Span>,
+    /// Edits to the source that solve the problem, when they can be found without guessing.
+    pub fix: Option<Fix>,
+}
+
+impl Diagnostic {
+    pub fn new<M: Into<String>>(severity: Severity, code: &'static str, message: M) -> Self {
+        Self {
+            severity,
+            code,
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+            file: PathBuf::new(),
+            span: None,
+            fix: None,
+        }
+    }
+
+    pub fn error<M: Into<String>>(code: &'static str, message: M) -> Self {
+        Self::new(Severity::Error, code, message)
+    }
+
+    pub fn warning<M: Into<String>>(code: &'static str, message: M) -> Self {
+        Self::new(Severity::Warning, code, message)
+    }
+
+    pub fn in_file<P: AsRef<Path>>(mut self, file: P) -> Self {
+        self.file = file.as_ref().to_path_buf();
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_label<M: Into<String>>(mut self, span: Span, message: M) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note<N: Into<String>>(mut self, note: N) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_fix(mut self, fix: Fix) -> Self {
+        self.fix = Some(fix);
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the diagnostic to stderr, showing the parts of `input` it points to.
+    pub fn eprint(&self, input: &str) {
+        let file = self.file.display().to_string();
+        let kind = match self.severity {
+            Severity::Error => ReportKind::Error,
+            Severity::Warning => ReportKind::Warning,
+            Severity::Note => ReportKind::Advice,
+        };
+        let color = match self.severity {
+            Severity::Error => Color::Red,
+            Severity::Warning => Color::Yellow,
+            Severity::Note => Color::Blue,
+        };
+
+        let offset = self.span.as_ref().map(|s| s.start).unwrap_or(0);
+        let mut report = Report::build(kind, file.clone(), offset)
+            .with_message(format!("[{}] {}", self.code, self.message));
+
+        for label in self.labels.iter() {
+            report = report.with_label(
+                ReportLabel::new((file.clone(), label.span.clone()))
+                    .with_message(&label.message)
+                    .with_color(color),
+            );
+        }
+
+        let notes = self
+            .notes
+            .iter()
+            .cloned()
+            .chain(
+                self.fix
+                    .iter()
+                    .map(|f| format!("Fix available: {}", f.message)),
+            )
+            .collect::<Vec<_>>();
+
+        if !notes.is_empty() {
+            report = report.with_note(notes.join("\n"));
+        }
+
+        report
+            .finish()
+            .eprint((file, Source::from(input)))
+            .unwrap_or_else(|_| eprintln!("{}: {}", self.severity, self.message));
+    }
+}
+
+/// Every diagnostic found in a ledger that couldn't be processed.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("found {} problem(s) in the ledger", .0.len())]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn eprint(&self, input: &str) {
+        self.0.iter().for_each(|d| d.eprint(input));
+    }
+}
+
+/// A position in the source, with lines and columns starting at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    /// The position of the character at `offset`, counted in characters like spans are.
+    pub fn of(input: &str, offset: usize) -> Self {
+        let mut position = Self { line: 1, column: 1 };
+
+        for c in input.chars().take(offset) {
+            if c == '\n' {
+                position.line += 1;
+                position.column = 1;
+            } else {
+                position.column += 1;
+            }
+        }
+
+        position
+    }
+
+    /// The character offset of this position in `input`, the inverse of [`Position::of`].
+    pub fn offset_in(&self, input: &str) -> usize {
+        let mut current = Self { line: 1, column: 1 };
+
+        for (offset, c) in input.chars().enumerate() {
+            if current >= *self {
+                return offset;
+            }
+
+            if c == '\n' {
+                if current.line == self.line {
+                    return offset;
+                }
+
+                current.line += 1;
+                current.column = 1;
+            } else {
+                current.column += 1;
+            }
+        }
+
+        input.chars().count()
+    }
+}
+
+/// Text that diagnostics point into: either a whole source, or an [`Excerpt`] of it.
+pub trait SourceText {
+    /// Where `span` starts and ends, ignoring the whitespace spans may include.
+    fn locate(&self, span: &Span) -> (Position, Position);
+
+    /// Renders `diagnostic` to stderr, showing the parts of the text it points to.
+    fn eprint(&self, diagnostic: &Diagnostic);
+}
+
+impl SourceText for str {
+    fn locate(&self, span: &Span) -> (Position, Position) {
+        let chars = self.chars().collect::<Vec<_>>();
+        let (mut start, mut end) = (span.start.min(chars.len()), span.end.min(chars.len()));
+
+        while start < end && chars[start].is_whitespace() {
+            start += 1;
+        }
+
+        while end > start && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+
+        (Position::of(self, start), Position::of(self, end))
+    }
+
+    fn eprint(&self, diagnostic: &Diagnostic) {
+        diagnostic.eprint(self)
+    }
+}
+
+/// The lines of a source that some diagnostics point at, for sources too large to keep in
+/// memory. Spans still count characters from the start of the whole source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Excerpt {
+    /// The number and text of each line kept, with its line break, by the offset of its first
+    /// character.
+    lines: BTreeMap<usize, (usize, String)>,
+}
+
+impl Excerpt {
+    /// Keeps `text`, the line numbered `line` that starts `offset` characters into the source.
+    pub fn add_line(&mut self, offset: usize, line: usize, text: String) {
+        self.lines.insert(offset, (line, text));
+    }
+
+    /// How many lines are kept.
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// The text of the line numbered `line`, without its line break, if it was kept.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.lines
+            .values()
+            .find(|(number, _)| *number == line)
+            .map(|(_, text)| text.trim_end_matches(['\r', '\n']))
+    }
+
+    /// The character at `offset` and its position, if it is in one of the lines kept.
+    fn get(&self, offset: usize) -> Option<(char, Position)> {
+        let (start, (line, text)) = self.lines.range(..=offset).next_back()?;
+        let column = offset - start;
+
+        text.chars().nth(column).map(|c| {
+            let position = Position {
+                line: *line,
+                column: column + 1,
+            };
+
+            (c, position)
+        })
+    }
+
+    /// The position of the character at `offset`, like [`Position::of`] in the whole source.
+    fn position(&self, offset: usize) -> Position {
+        match self.get(offset) {
+            Some((_, position)) => position,
+            // Past the end of a line kept, so right after its line break.
+            None => match self.lines.range(..=offset).next_back() {
+                Some((start, (line, text))) if text.ends_with('\n') => Position {
+                    line: line + 1,
+                    column: offset - start - text.chars().count() + 1,
+                },
+                Some((start, (line, _))) => Position {
+                    line: *line,
+                    column: offset - start + 1,
+                },
+                None => Position { line: 1, column: 1 },
+            },
+        }
+    }
+}
+
+impl SourceText for Excerpt {
+    fn locate(&self, span: &Span) -> (Position, Position) {
+        let is_space = |offset| self.get(offset).is_some_and(|(c, _)| c.is_whitespace());
+        let (mut start, mut end) = (span.start, span.end);
+
+        while start < end && is_space(start) {
+            start += 1;
+        }
+
+        while end > start && is_space(end - 1) {
+            end -= 1;
+        }
+
+        (self.position(start), self.position(end))
+    }
+
+    /// Renders like the compiler does, since the reports of [`Diagnostic::eprint`] need the
+    /// whole source to number its lines.
+    fn eprint(&self, diagnostic: &Diagnostic) {
+        let located = diagnostic
+            .labels
+            .iter()
+            .map(|label| (self.locate(&label.span), &label.message))
+            .collect::<Vec<_>>();
+        let width = located
+            .iter()
+            .map(|((start, _), _)| start.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+
+        eprintln!(
+            "{}[{}]: {}",
+            diagnostic.severity, diagnostic.code, diagnostic.message
+        );
+
+        if let Some((start, _)) = diagnostic.range(self) {
+            eprintln!(
+                "{:width$}--> {}:{}:{}",
+                "",
+                diagnostic.file.display(),
+                start.line,
+                start.column,
+                width = width
+            );
+        }
+
+        for ((start, end), message) in located {
+            let text = self.line(start.line).unwrap_or_default();
+            // Labels over many lines are marked up to the end of their first one.
+            let columns = match start.line == end.line {
+                true => end.column.saturating_sub(start.column).max(1),
+                false => (text.chars().count() + 1)
+                    .saturating_sub(start.column)
+                    .max(1),
+            };
+
+            eprintln!("{:>width$} | {}", start.line, text, width = width);
+            eprintln!(
+                "{:>width$} | {}{} {}",
+                "",
+                " ".repeat(start.column - 1),
+                "^".repeat(columns),
+                message,
+                width = width
+            );
+        }
+
+        let fix = diagnostic
+            .fix
+            .iter()
+            .map(|f| format!("Fix available: {}", f.message));
+
+        for note in diagnostic.notes.iter().cloned().chain(fix) {
+            eprintln!("{:width$} = {}", "", note, width = width);
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn range<S: SourceText + ?Sized>(&self, input: &S) -> Option<(Position, Position)> {
+        self.span.as_ref().map(|span| input.locate(span))
+    }
+
+    pub fn to_json<S: SourceText + ?Sized>(&self, input: &S) -> Value {
+        let range = self.range(input);
+
+        json!({
+            "file": self.file.display().to_string(),
+            "line": range.map(|(s, _)| s.line),
+            "column": range.map(|(s, _)| s.column),
+            "end_line": range.map(|(_, e)| e.line),
+            "end_column": range.map(|(_, e)| e.column),
+            "severity": self.severity.to_string(),
+            "code": self.code,
+            "message": self.message,
+            "labels": self.labels.iter().map(|l| {
+                let (start, end) = input.locate(&l.span);
+
+                json!({
+                    "line": start.line,
+                    "column": start.column,
+                    "end_line": end.line,
+                    "end_column": end.column,
+                    "message": l.message,
+                })
+            }).collect::<Vec<_>>(),
+            "notes": self.notes,
+        })
+    }
+
+    fn to_sarif<S: SourceText + ?Sized>(&self, input: &S) -> Value {
+        let level = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        };
+
+        let mut location = json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": self.file.display().to_string() },
+            }
+        });
+
+        if let Some((start, end)) = self.range(input) {
+            location["physicalLocation"]["region"] = json!({
+                "startLine": start.line,
+                "startColumn": start.column,
+                "endLine": end.line,
+                "endColumn": end.column,
+            });
+        }
+
+        json!({
+            "ruleId": self.code,
+            "level": level,
+            "message": { "text": self.message },
+            "locations": [location],
+        })
+    }
+}
+
+/// How command line tools print diagnostics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Colored reports with the source, for people.
+    Human,
+    /// One JSON object per diagnostic, in an array.
+    Json,
+    /// A SARIF 2.1.0 log, understood by most code scanning tools.
+    Sarif,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "human" => Ok(Self::Human),
+            "json" => Ok(Self::Json),
+            "sarif" => Ok(Self::Sarif),
+            _ => Err(format!(
+                "Unknown format `{}`, expected human, json or sarif",
+                value
+            )),
+        }
+    }
+}
+
+pub fn to_json<S: SourceText + ?Sized>(diagnostics: &[Diagnostic], input: &S) -> Value {
+    Value::Array(diagnostics.iter().map(|d| d.to_json(input)).collect())
+}
+
+fn rule(code: &str) -> Value {
+    match explain(code) {
+        Some(info) => json!({
+            "id": info.code,
+            "name": info.title,
+            "shortDescription": { "text": info.title },
+            "help": { "text": info.explanation },
+        }),
+        None => json!({ "id": code }),
+    }
+}
+
+pub fn to_sarif<S: SourceText + ?Sized>(diagnostics: &[Diagnostic], input: &S) -> Value {
+    let mut codes = diagnostics.iter().map(|d| d.code).collect::<Vec<_>>();
+    codes.sort_unstable();
+    codes.dedup();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "hortela",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": codes.iter().map(|c| rule(c)).collect::<Vec<_>>(),
+                }
+            },
+            "columnKind": "unicodeCodePoints",
+            "results": diagnostics.iter().map(|d| d.to_sarif(input)).collect::<Vec<_>>(),
+        }]
+    })
+}
+
+/// Prints every diagnostic in `format`: human reports go to stderr, the others to stdout.
+pub fn emit<S: SourceText + ?Sized>(diagnostics: &[Diagnostic], input: &S, format: OutputFormat) {
+    match format {
+        OutputFormat::Human => diagnostics.iter().for_each(|d| input.eprint(d)),
+        OutputFormat::Json => println!("{}", to_json(diagnostics, input)),
+        OutputFormat::Sarif => println!("{:#}", to_sarif(diagnostics, input)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUT: &str = "2020-01-01 open assets:cash BRL\n2020-01-02 balance assets:cash 1 BRL\n";
+
+    #[test]
+    fn test_position() {
+        assert_eq!(Position::of(INPUT, 0), Position { line: 1, column: 1 });
+        assert_eq!(Position::of(INPUT, 32), Position { line: 2, column: 1 });
+        assert_eq!(
+            Position::of(INPUT, 43),
+            Position {
+                line: 2,
+                column: 12
+            }
+        );
+    }
+
+    #[test]
+    fn test_excerpt_positions() {
+        let mut excerpt = Excerpt::default();
+        excerpt.add_line(32, 2, INPUT.lines().nth(1).unwrap().to_string() + "\n");
+
+        for offset in 32..INPUT.chars().count() {
+            assert_eq!(excerpt.position(offset), Position::of(INPUT, offset));
+        }
+
+        assert_eq!(excerpt.locate(&(32..69)), INPUT.locate(&(32..69)));
+        assert_eq!(
+            excerpt.line(2),
+            Some("2020-01-02 balance assets:cash 1 BRL")
+        );
+        assert_eq!(excerpt.line(1), None);
+    }
+
+    #[test]
+    fn test_to_json() {
+        let diagnostic = Diagnostic::error("balance-mismatch", "Balance does not match")
+            .in_file("books.hta")
+            .with_label(32..69, "Here")
+            .with_span(32..69);
+
+        let value = diagnostic.to_json(INPUT);
+
+        assert_eq!(value["file"], "books.hta");
+        assert_eq!(value["severity"], "error");
+        assert_eq!(value["code"], "balance-mismatch");
+        assert_eq!(value["line"], 2);
+        assert_eq!(value["column"], 1);
+        assert_eq!(value["end_line"], 2);
+        assert_eq!(value["end_column"], 37);
+        assert_eq!(value["labels"][0]["message"], "Here");
+    }
+
+    #[test]
+    fn test_to_sarif() {
+        let diagnostics =
+            vec![Diagnostic::warning("some-warning", "Something").in_file("books.hta")];
+        let sarif = to_sarif(&diagnostics, INPUT);
+
+        let result = &sarif["runs"][0]["results"][0];
+
+        assert_eq!(sarif["version"], "2.1.0");
+        assert_eq!(result["ruleId"], "some-warning");
+        assert_eq!(result["level"], "warning");
+        assert!(result["locations"][0]["physicalLocation"]["region"].is_null());
+    }
+}
@@ -0,0 +1,238 @@
+//! Applies the fixes suggested by diagnostics to the source they were found in.
+//!
+//! Edits are placed with the lossless [`tokenize`], so the whitespace and comments that spans
+//! may include are kept as written. A fix that touches text already changed by another one is
+//! skipped as a whole, and is usually suggested again once the fixed ledger is checked.
+
+use crate::{
+    diagnostic::{Diagnostic, TextEdit},
+    syntax::{cst::tokenize, Span},
+};
+
+/// The part of `span` with tokens, without the whitespace and comments around them.
+fn content(chars: &[char], span: &Span) -> Span {
+    let (start, end) = (span.start.min(chars.len()), span.end.min(chars.len()));
+    let text = chars[start..end.max(start)].iter().collect::<String>();
+    let tokens = tokenize(&text);
+    let mut tokens = tokens.iter().filter(|t| !t.is_trivia());
+
+    match tokens.next() {
+        Some(first) => {
+            let last = tokens.next_back().unwrap_or(first);
+            start + first.span.start..start + last.span.end
+        }
+        None => start..start,
+    }
+}
+
+/// The characters an edit replaces, along with the text that goes in their place.
+fn resolve(chars: &[char], edit: &TextEdit) -> (Span, String) {
+    match edit {
+        TextEdit::Replace(span, text) => (content(chars, span), text.clone()),
+        TextEdit::AddLines(span, text) => {
+            let end = content(chars, span).end;
+
+            match chars[end..].iter().position(|c| *c == '\n') {
+                Some(n) => (end + n + 1..end + n + 1, text.clone()),
+                None => (chars.len()..chars.len(), format!("\n{}", text)),
+            }
+        }
+    }
+}
+
+/// Applies the fixes of `diagnostics`, which must all be about `input`, returning the fixed
+/// text and how many fixes were applied.
+pub fn apply_fixes(input: &str, diagnostics: &[Diagnostic]) -> (String, usize) {
+    let chars = input.chars().collect::<Vec<_>>();
+    let mut edits: Vec<(Span, String)> = vec![];
+    let mut applied = 0;
+
+    for fix in diagnostics.iter().filter_map(|d| d.fix.as_ref()) {
+        let resolved = fix
+            .edits
+            .iter()
+            .map(|edit| resolve(&chars, edit))
+            .collect::<Vec<_>>();
+
+        // Edits that touch, like two insertions at the same place, conflict too.
+        let conflicts = resolved.iter().enumerate().any(|(i, (a, _))| {
+            edits
+                .iter()
+                .chain(resolved[..i].iter())
+                .any(|(b, _)| a.start <= b.end && b.start <= a.end)
+        });
+
+        if !conflicts {
+            edits.extend(resolved);
+            applied += 1;
+        }
+    }
+
+    edits.sort_by_key(|(span, _)| span.start);
+
+    let mut output = String::new();
+    let mut pos = 0;
+
+    for (span, text) in edits {
+        output.extend(&chars[pos..span.start]);
+        output.push_str(&text);
+        pos = span.end;
+    }
+
+    output.extend(&chars[pos..]);
+
+    (output, applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        codes, compute_program, diagnostic::Fix, syntax::parse_string, validate::ValidationRunner,
+    };
+
+    fn check(input: &str) -> anyhow::Result<Vec<Diagnostic>> {
+        let filename = Path::new("books.hta");
+        let (ledger, context) = compute_program(parse_string(filename, input)?)?;
+
+        ValidationRunner::default().run(filename, &ledger, &context)
+    }
+
+    #[test]
+    fn test_edits_keep_comments() {
+        let input = "2020-01-01 balance assets:cash 1 BRL // checked\n";
+        let fix = |edit| {
+            Diagnostic::error(codes::BALANCE_MISMATCH, "").with_fix(Fix::new("", vec![edit]))
+        };
+
+        let diagnostics = vec![
+            fix(TextEdit::Replace(
+                31..input.chars().count(),
+                "2.00 BRL".into(),
+            )),
+            fix(TextEdit::AddLines(0..11, "// Added\n".into())),
+            // Replaces text already changed by the first fix.
+            fix(TextEdit::Replace(33..37, "USD".into())),
+        ];
+
+        assert_eq!(
+            apply_fixes(input, &diagnostics),
+            (
+                "2020-01-01 balance assets:cash 2.00 BRL // checked\n// Added\n".to_string(),
+                2
+            )
+        );
+        assert_eq!(
+            apply_fixes(input.trim_end(), &diagnostics[1..2]).0,
+            "2020-01-01 balance assets:cash 1 BRL // checked\n// Added\n"
+        );
+    }
+
+    #[test]
+    fn test_apply_fixes() -> anyhow::Result<()> {
+        let input = r#"2020-01-01 open assets:cash BRL
+2020-01-01 open expenses:food
+2020-01-01 open income:salary
+2020-01-02 transaction "Salary"
+  > 100 BRL income:salary
+  < 100 BRL assets:cash
+2020-01-03 balance assets:cash 90 BRL
+2020-01-04 transaction "Market"
+  > 30 BRL assets:cash
+  < 20.5 BRL expenses:food // lunch
+2020-01-05 transaction "Exchange"
+  > 10 BRL assets:cash
+  < 2 USD assets:cash
+"#;
+        let diagnostics = check(input)?;
+        let fixes = diagnostics
+            .iter()
+            .filter_map(|d| Some((d.code, d.fix.as_ref()?.message.as_str())))
+            .collect::<Vec<_>>();
+
+        // Transactions in more than one currency have no single residual to add.
+        assert_eq!(
+            fixes,
+            vec![
+                (
+                    codes::UNBALANCED_TRANSACTION,
+                    "Add `< 9.5 BRL expenses:food` to balance the transaction"
+                ),
+                (codes::BALANCE_MISMATCH, "Change the amount to 100.00 BRL"),
+            ]
+        );
+
+        let (fixed, applied) = apply_fixes(input, &diagnostics);
+
+        assert_eq!(applied, 2);
+        assert_eq!(
+            fixed,
+            r#"2020-01-01 open assets:cash BRL
+2020-01-01 open expenses:food
+2020-01-01 open income:salary
+2020-01-02 transaction "Salary"
+  > 100 BRL income:salary
+  < 100 BRL assets:cash
+2020-01-03 balance assets:cash 100.00 BRL
+2020-01-04 transaction "Market"
+  > 30 BRL assets:cash
+  < 20.5 BRL expenses:food // lunch
+  < 9.5 BRL expenses:food
+2020-01-05 transaction "Exchange"
+  > 10 BRL assets:cash
+  < 2 USD assets:cash
+"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_unopened_accounts() -> anyhow::Result<()> {
+        let input = r#"2020-01-01 open assets:cash BRL
+2020-01-01 open expenses:food
+2020-01-02 transaction "Salary"
+  > 100 BRL income:salary
+  < 100 BRL assets:cash
+2020-01-03 transaction "Market"
+  > 30 BRL assets:cash
+  < 30 BRL expenses:fod
+2020-01-04 transaction "Bonus"
+  > 10 BRL income:salary
+  < 10 BRL assets:cash
+"#;
+        let diagnostics = check(input)?;
+        let (fixed, applied) = apply_fixes(input, &diagnostics);
+
+        // Ledgers that never opened their accounts are still valid, so these are only warnings.
+        assert!(diagnostics
+            .iter()
+            .filter(|d| d.code == codes::UNOPENED_ACCOUNT)
+            .all(|d| !d.is_error()));
+        assert_eq!(applied, 2);
+        assert_eq!(
+            fixed,
+            r#"2020-01-01 open assets:cash BRL
+2020-01-01 open expenses:food
+2020-01-02 open income:salary
+2020-01-02 transaction "Salary"
+  > 100 BRL income:salary
+  < 100 BRL assets:cash
+2020-01-03 open expenses:fod
+2020-01-03 transaction "Market"
+  > 30 BRL assets:cash
+  < 30 BRL expenses:fod
+2020-01-04 transaction "Bonus"
+  > 10 BRL income:salary
+  < 10 BRL assets:cash
+"#
+        );
+        assert!(check(&fixed)?
+            .iter()
+            .all(|d| d.code != codes::UNOPENED_ACCOUNT));
+
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use std::ops::{BitAnd, Not};
+use std::{
+    ops::{BitAnd, Not},
+    path::Path,
+};
 
 use chrono::{NaiveDate, NaiveDateTime};
 use num::ToPrimitive;
@@ -6,7 +9,9 @@ use polars::prelude::*;
 
 use crate::{
     account::Account,
+    diagnostic::Diagnostic,
     money::{Money, MovementKind},
+    options::LedgerOptions,
     syntax::Span,
     BalanceVerification,
 };
@@ -18,6 +23,8 @@ pub struct Transaction {
     pub description: String,
     pub kind: MovementKind,
     pub account: Account,
+    /// The name `account` had on `date`, before any later renames.
+    pub historical_account: Account,
     pub amount: Money,
     pub span: Span,
     pub from_amount: Option<Money>,
@@ -36,12 +43,15 @@ pub struct Ledger {
     pub date: Series,
     pub description: Series,
     pub account_name: Series,
+    pub historical_account_name: Series,
     pub account_name_0: Series,
     pub account_name_1: Series,
     pub account_name_2: Series,
     pub account_name_3: Series,
     pub amount_numerator: Series,
     pub amount_denominator: Series,
+    /// Amounts as exact rationals like `201/20`, as their parts may not fit the columns above.
+    pub exact_amount: Series,
     pub currency: Series,
     pub amount_from_numerator: Series,
     pub amount_from_denominator: Series,
@@ -76,6 +86,12 @@ impl From<Vec<Transaction>> for Ledger {
                     .map(|x| x.account.to_string())
                     .collect::<Vec<_>>(),
             ),
+            historical_account_name: Series::new(
+                "ledger.historical_account_name",
+                iter.clone()
+                    .map(|x| x.historical_account.to_string())
+                    .collect::<Vec<_>>(),
+            ),
             account_name_0: Series::new(
                 "ledger.account_name_0",
                 iter.clone()
@@ -108,6 +124,12 @@ impl From<Vec<Transaction>> for Ledger {
                 "ledger.amount_denominator",
                 iter.clone().map(|x| x.amount.denom()).collect::<Vec<_>>(),
             ),
+            exact_amount: Series::new(
+                "ledger.exact_amount",
+                iter.clone()
+                    .map(|x| x.amount.amount.to_string())
+                    .collect::<Vec<_>>(),
+            ),
             currency: Series::new(
                 "ledger.currency",
                 iter.clone()
@@ -181,7 +203,10 @@ impl Ledger {
         Ok(df.filter(&df.column("ledger.is_credit")?.bool()?.not())?)
     }
 
-    pub fn transaction_type_mask(&self, df: &DataFrame) -> Result<(BooleanChunked, BooleanChunked)> {
+    pub fn transaction_type_mask(
+        &self,
+        df: &DataFrame,
+    ) -> Result<(BooleanChunked, BooleanChunked)> {
         let column = df.column("ledger.is_credit")?.bool()?;
 
         Ok((column.clone(), column.not()))
@@ -202,6 +227,7 @@ impl Ledger {
             data.date,
             data.description,
             data.account_name,
+            data.historical_account_name,
             data.account_name_0,
             data.account_name_1,
             data.account_name_2,
@@ -209,6 +235,7 @@ impl Ledger {
             amount,
             data.amount_numerator,
             data.amount_denominator,
+            data.exact_amount,
             data.currency,
             data.amount_from_numerator,
             data.amount_from_denominator,
@@ -221,15 +248,21 @@ impl Ledger {
         ])
     }
 
-    pub fn validate_balances(&self, list: Vec<BalanceVerification>) -> Result<()> {
-        for verification in list {
-            print!(
-                "Verifying balance for {} on {}...",
-                verification.account, verification.date
-            );
-
-            let df = self.all()?;
+    /// Checks every `balance` op against the ledger, reporting the ones that do not match.
+    ///
+    /// Only movements in the currency of the op count, so an account that holds several
+    /// currencies has a `balance` op for each, and amounts of different currencies are never
+    /// added up.
+    pub fn validate_balances(
+        &self,
+        filename: &Path,
+        list: Vec<BalanceVerification>,
+        options: &LedgerOptions,
+    ) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = vec![];
+        let df = self.all()?;
 
+        for verification in list {
             let acc: &str = &verification.account.to_string();
             let filter_mask = df.column("ledger.account_name")?.equal(acc);
 
@@ -238,25 +271,20 @@ impl Ledger {
                 .date()?
                 .lt_eq(date_to_arrow_datatype(verification.date));
 
-            let filtered = df.filter(&filter_mask.bitand(date_mask))?;
+            let currency_mask = df
+                .column("ledger.currency")?
+                .equal(verification.amount.currency().as_str());
+
+            let filtered = df.filter(&filter_mask.bitand(date_mask).bitand(currency_mask))?;
 
             let sum = filtered
                 .column("ledger.signed_amount")?
                 .sum()
                 .unwrap_or(0.0);
 
-            // TODO: Make proper rounding for the numbers to avoid those kinds of hacks
-            if !verification.amount.equals(sum, 2) {
-                println!(" ERROR");
-                panic!(
-                    "Balances do not match, expected {}, got {}",
-                    verification.amount, sum
-                );
-            }
-
-            println!(" OK");
+            diagnostics.extend(verification.check(filename, sum, options));
         }
 
-        Ok(())
+        Ok(diagnostics)
     }
 }
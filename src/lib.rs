@@ -1,17 +1,41 @@
-use account::Account;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+};
+
+use account::{Account, AccountHistory, Rename};
 use anyhow::Result;
+use assertion::LedgerAssertion;
 use chrono::prelude::*;
+use num::ToPrimitive;
 
 pub mod account;
+pub mod activity;
+pub mod analysis;
+pub mod assertion;
+pub mod budget;
+pub mod cache;
+pub mod chart;
+pub mod codes;
+pub mod diagnostic;
+pub mod fix;
 pub mod ledger;
 pub mod money;
+pub mod options;
+pub mod register;
+pub mod stream;
 pub mod syntax;
 pub mod utils;
 pub mod validate;
 
+use budget::Budget;
+use chart::AccountOpening;
+use diagnostic::{Diagnostic, Fix, TextEdit};
 use ledger::{Ledger, Transaction};
 use money::Money;
-use syntax::{Op, Span, Spanned};
+use options::{LedgerOption, LedgerOptions};
+use register::{AccountDocument, AccountNote};
+use syntax::{Metadata, Op, Span, Spanned};
 
 #[derive(Debug, Clone)]
 pub struct BalanceVerification {
@@ -19,22 +43,99 @@ pub struct BalanceVerification {
     pub date: NaiveDate,
     pub amount: Money,
     pub span: Span,
+    /// Where the amount is written, which fixes replace.
+    pub amount_span: Span,
 }
 
 impl BalanceVerification {
-    pub fn new(account: Account, date: NaiveDate, amount: Money, span: Span) -> Self {
+    pub fn new(
+        account: Account,
+        date: NaiveDate,
+        amount: Money,
+        span: Span,
+        amount_span: Span,
+    ) -> Self {
         Self {
             account,
             date,
             amount,
             span,
+            amount_span,
         }
     }
+
+    /// Compares the directive with `sum`, the balance computed from the ledger.
+    pub fn check(&self, filename: &Path, sum: f64, options: &LedgerOptions) -> Option<Diagnostic> {
+        let expected = self.amount.amount.to_f64().unwrap_or(f64::NAN);
+
+        if options.equals(expected, sum) {
+            return None;
+        }
+
+        let precision = options.display_precision as usize;
+        let found = format!("{:.2$} {}", sum, self.amount.currency(), precision);
+
+        // Amounts are shown rounded, and balances can't be written negative.
+        let rounded = format!("{:.1$}", sum, precision);
+        let fix = rounded
+            .parse()
+            .ok()
+            .filter(|r| !rounded.starts_with('-') && options.equals(*r, sum))
+            .map(|_| {
+                Fix::new(
+                    format!("Change the amount to {}", found),
+                    vec![TextEdit::Replace(self.amount_span.clone(), found.clone())],
+                )
+            });
+
+        let diagnostic = Diagnostic::error(
+            codes::BALANCE_MISMATCH,
+            format!(
+                "Balance for `{}` on {} does not match, expected `{}`, found {}",
+                self.account, self.date, self.amount, found
+            ),
+        )
+        .in_file(filename)
+        .with_label(
+            self.span.clone(),
+            format!("The ledger has {} at this point", found),
+        )
+        .with_span(self.span.clone());
+
+        Some(match fix {
+            Some(fix) => diagnostic.with_fix(fix),
+            None => diagnostic,
+        })
+    }
 }
 
 #[derive(Default)]
 pub struct LedgerContext {
+    pub options: LedgerOptions,
+    pub opens: Vec<AccountOpening>,
     pub balance_verifications: Vec<BalanceVerification>,
+    pub assertions: Vec<LedgerAssertion>,
+    pub budgets: Vec<Budget>,
+    pub notes: Vec<AccountNote>,
+    pub documents: Vec<AccountDocument>,
+    pub history: AccountHistory,
+    /// Metadata of the transactions that have any, by `ledger.parent_id`.
+    pub transaction_metadata: HashMap<u64, Metadata>,
+    /// The date and span of every op that has a date, in the order they were written.
+    pub dated_ops: Vec<Spanned<NaiveDate>>,
+    /// Where each `rule.*` option was written, by rule name.
+    pub rule_spans: BTreeMap<String, Span>,
+}
+
+impl LedgerContext {
+    /// Applies `option`, remembering where rules are configured.
+    pub fn set_option(&mut self, option: LedgerOption, span: Span) {
+        if let LedgerOption::Rule(name, _) = &option {
+            self.rule_spans.insert(name.clone(), span);
+        }
+
+        self.options.set(option);
+    }
 }
 
 pub fn compute_program(program: Vec<Spanned<Op>>) -> Result<(Ledger, LedgerContext)> {
@@ -42,25 +143,99 @@ pub fn compute_program(program: Vec<Spanned<Op>>) -> Result<(Ledger, LedgerConte
     let mut result: Vec<Transaction> = vec![];
     let mut id: u64 = 1;
 
+    // Renames apply to the whole ledger, including entries written before them.
+    for (expr, span) in program.iter() {
+        if let Op::Rename((date, _), (from, _), (to, _)) = expr {
+            context.history.renames.push(Rename::new(
+                *date,
+                from.clone(),
+                to.clone(),
+                span.clone(),
+            ));
+        }
+    }
+
+    let history = context.history.clone();
+
     for (expr, span) in program.into_iter() {
+        if let Some(date) = expr.date() {
+            context.dated_ops.push((date, span.clone()));
+        }
+
         match expr {
-            Op::Open(_date, _acc, _balance) => {}
-            Op::Balance((date, _), (account, _), (amount, _)) => {
-                context
-                    .balance_verifications
-                    .push(BalanceVerification::new(account, date, amount, span));
+            Op::Open((date, _), (account, _), currencies, metadata) => {
+                context.opens.push(AccountOpening::new(
+                    date,
+                    history.canonical(&account),
+                    currencies.into_iter().map(|(c, _)| c).collect(),
+                    metadata.into_iter().map(|(m, _)| m).collect(),
+                    span,
+                ));
+            }
+            Op::Balance((date, _), (account, _), (amount, amount_span)) => {
+                context.balance_verifications.push(BalanceVerification::new(
+                    history.canonical(&account),
+                    date,
+                    amount,
+                    span,
+                    amount_span,
+                ));
             }
-            Op::Transaction((date, _), (desc, _), (movements, _)) => {
+            Op::Transaction((date, _), (desc, _), (movements, _), metadata) => {
                 let parent = Some(id);
 
+                if !metadata.is_empty() {
+                    context
+                        .transaction_metadata
+                        .insert(id, metadata.into_iter().map(|(m, _)| m).collect());
+                }
+
                 for (movement, span) in movements.into_iter() {
-                    let transaction = movement.to_transaction(id, date, desc.clone(), span, parent);
+                    let mut transaction =
+                        movement.to_transaction(id, date, desc.clone(), span, parent);
+
+                    transaction.account = history.canonical(&transaction.account);
+                    transaction.historical_account = history.name_at(&transaction.account, date);
 
                     result.push(transaction);
 
                     id += 1;
                 }
             }
+            Op::Budget((account, _), (amount, _), (period, _)) => {
+                context.budgets.push(Budget::new(
+                    history.canonical(&account),
+                    amount,
+                    period,
+                    span,
+                ));
+            }
+            Op::Note((date, _), (account, _), (text, _)) => {
+                context.notes.push(AccountNote::new(
+                    date,
+                    history.canonical(&account),
+                    text,
+                    span,
+                ));
+            }
+            Op::Document((date, _), (account, _), (path, _)) => {
+                context.documents.push(AccountDocument::new(
+                    date,
+                    history.canonical(&account),
+                    path,
+                    span,
+                ));
+            }
+            Op::Option((option, _)) => context.set_option(option, span),
+            Op::Assert(date, (assertion, _)) => {
+                context.assertions.push(LedgerAssertion::new(
+                    date.map(|(date, _)| date),
+                    assertion.canonical(&history),
+                    span,
+                ));
+            }
+            // Aliases are resolved while parsing, and renames were collected above.
+            Op::Alias(..) | Op::Rename(..) => {}
         }
     }
 
@@ -1,9 +1,10 @@
 use chrono::NaiveDate;
 use num::{BigRational, ToPrimitive};
+use serde::{Deserialize, Serialize};
 
-use crate::{account::Account, ledger::Transaction, syntax::Span};
+use crate::{account::Account, ledger::Transaction, syntax::Span, utils::format_decimal};
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
 pub struct Money {
     pub amount: BigRational,
     pub currency: Currency,
@@ -43,11 +44,11 @@ impl Money {
 impl std::fmt::Display for Money {
     // This trait requires `fmt` with this exact signature.
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{} {}", self.amount, self.currency.0)
+        write!(f, "{} {}", format_decimal(&self.amount), self.currency.0)
     }
 }
 
-#[derive(PartialEq, Eq, Clone, Debug, Hash)]
+#[derive(PartialEq, Eq, Clone, Debug, Hash, Serialize, Deserialize)]
 pub struct Currency(pub String);
 
 impl From<Currency> for String {
@@ -68,13 +69,22 @@ impl From<&str> for Currency {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Eq, Hash, Copy)]
+#[derive(Debug, PartialEq, Clone, Eq, Hash, Copy, Serialize, Deserialize)]
 pub enum MovementKind {
     Credit,
     Debit,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash)]
+impl std::fmt::Display for MovementKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MovementKind::Credit => write!(f, ">"),
+            MovementKind::Debit => write!(f, "<"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
 pub struct Movement(pub MovementKind, pub Money, pub Account);
 
 impl Movement {
@@ -111,6 +121,7 @@ impl Movement {
             date,
             description,
             kind: self.0,
+            historical_account: self.2.clone(),
             account: self.2,
             amount: self.1,
             span,
@@ -119,3 +130,9 @@ impl Movement {
         }
     }
 }
+
+impl std::fmt::Display for Movement {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.0, self.1, self.2)
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate};
+use num::{BigRational, Signed, ToPrimitive, Zero};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    account::AccountType,
+    diagnostic::Severity,
+    money::Currency,
+    utils::{format_decimal, parse_decimal},
+};
+
+/// The day on which the fiscal year starts, used for quarterly and yearly periods.
+///
+/// Days are limited to 28 so that the same day exists in every month.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash, Serialize, Deserialize)]
+pub struct FiscalYearStart {
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Default for FiscalYearStart {
+    fn default() -> Self {
+        Self { month: 1, day: 1 }
+    }
+}
+
+impl FiscalYearStart {
+    /// The start of the fiscal year that contains `date`.
+    pub fn year_of(&self, date: NaiveDate) -> NaiveDate {
+        let start = NaiveDate::from_ymd(date.year(), self.month, self.day);
+
+        if start <= date {
+            start
+        } else {
+            NaiveDate::from_ymd(date.year() - 1, self.month, self.day)
+        }
+    }
+}
+
+impl std::fmt::Display for FiscalYearStart {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:02}-{:02}", self.month, self.day)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+pub enum LedgerOption {
+    OperatingCurrency(Currency),
+    FiscalYearStart(FiscalYearStart),
+    DefaultTolerance(BigRational),
+    DisplayPrecision(u32),
+    DuplicateWindow(u32),
+    StrictDateOrder(bool),
+    /// Turns a validator off with `None`, or changes the severity of what it reports.
+    Rule(String, Option<Severity>),
+    /// The only second-level names allowed under a root account, like `expenses`.
+    AccountCategories(String, Vec<String>),
+    /// A regular expression that the names of accounts under a root must match.
+    AccountPattern(String, String),
+    MaxAccountDepth(u32),
+}
+
+impl LedgerOption {
+    pub fn parse(key: &str, value: &str) -> Result<Self, String> {
+        match key {
+            "operating_currency" => {
+                if value.len() >= 3 && value.chars().all(|c| c.is_ascii_uppercase()) {
+                    Ok(Self::OperatingCurrency(value.into()))
+                } else {
+                    Err(format!("`{}` is not a valid currency", value))
+                }
+            }
+            "fiscal_year_start" => {
+                let invalid = || format!("Expected a date like `04-01`, found `{}`", value);
+
+                let (month, day) = value.split_once('-').ok_or_else(invalid)?;
+                let month = month.parse::<u32>().map_err(|_| invalid())?;
+                let day = day.parse::<u32>().map_err(|_| invalid())?;
+
+                if !(1..=12).contains(&month) || !(1..=28).contains(&day) {
+                    return Err(invalid());
+                }
+
+                Ok(Self::FiscalYearStart(FiscalYearStart { month, day }))
+            }
+            "default_tolerance" => parse_decimal(value)
+                .map(Self::DefaultTolerance)
+                .ok_or_else(|| format!("`{}` is not a valid tolerance", value)),
+            "display_precision" => value
+                .parse::<u32>()
+                .map(Self::DisplayPrecision)
+                .map_err(|_| format!("`{}` is not a valid precision", value)),
+            "duplicate_window" => value
+                .parse::<u32>()
+                .map(Self::DuplicateWindow)
+                .map_err(|_| format!("`{}` is not a valid number of days", value)),
+            "strict_date_order" => value
+                .parse::<bool>()
+                .map(Self::StrictDateOrder)
+                .map_err(|_| format!("Expected true or false, found `{}`", value)),
+            "max_account_depth" => value
+                .parse::<u32>()
+                .map(Self::MaxAccountDepth)
+                .map_err(|_| format!("`{}` is not a valid depth", value)),
+            _ if key.starts_with("account_categories.") || key.starts_with("account_pattern.") => {
+                let (prefix, root) = key.split_once('.').unwrap_or_default();
+
+                if root.parse::<AccountType>().is_err() {
+                    return Err(format!("`{}` is not a root account", root));
+                }
+
+                if prefix == "account_categories" {
+                    let categories = value.split_whitespace().map(String::from).collect();
+                    return Ok(Self::AccountCategories(root.to_string(), categories));
+                }
+
+                Regex::new(value)
+                    .map(|_| Self::AccountPattern(root.to_string(), value.to_string()))
+                    .map_err(|e| format!("`{}` is not a valid pattern: {}", value, e))
+            }
+            _ => match key.strip_prefix("rule.") {
+                Some(name) if !name.is_empty() => match value {
+                    "off" => Ok(Self::Rule(name.to_string(), None)),
+                    _ => value
+                        .parse::<Severity>()
+                        .map(|s| Self::Rule(name.to_string(), Some(s)))
+                        .map_err(|_| {
+                            format!(
+                                "Expected off, error, warning or note for rule `{}`, found `{}`",
+                                name, value
+                            )
+                        }),
+                },
+                _ => Err(format!("Unknown option `{}`", key)),
+            },
+        }
+    }
+
+    pub fn key(&self) -> String {
+        match self {
+            Self::OperatingCurrency(_) => "operating_currency".to_string(),
+            Self::FiscalYearStart(_) => "fiscal_year_start".to_string(),
+            Self::DefaultTolerance(_) => "default_tolerance".to_string(),
+            Self::DisplayPrecision(_) => "display_precision".to_string(),
+            Self::DuplicateWindow(_) => "duplicate_window".to_string(),
+            Self::StrictDateOrder(_) => "strict_date_order".to_string(),
+            Self::Rule(name, _) => format!("rule.{}", name),
+            Self::AccountCategories(root, _) => format!("account_categories.{}", root),
+            Self::AccountPattern(root, _) => format!("account_pattern.{}", root),
+            Self::MaxAccountDepth(_) => "max_account_depth".to_string(),
+        }
+    }
+
+    /// The value as written in an `option` directive.
+    pub fn value(&self) -> String {
+        match self {
+            Self::OperatingCurrency(c) => c.0.clone(),
+            Self::FiscalYearStart(f) => f.to_string(),
+            Self::DefaultTolerance(t) => format_decimal(t),
+            Self::DisplayPrecision(p) => p.to_string(),
+            Self::DuplicateWindow(d) => d.to_string(),
+            Self::StrictDateOrder(s) => s.to_string(),
+            Self::Rule(_, Some(severity)) => severity.to_string(),
+            Self::Rule(_, None) => "off".to_string(),
+            Self::AccountCategories(_, categories) => categories.join(" "),
+            Self::AccountPattern(_, pattern) => pattern.clone(),
+            Self::MaxAccountDepth(d) => d.to_string(),
+        }
+    }
+}
+
+/// Ledger-wide settings, set with `option` directives.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LedgerOptions {
+    /// The currency reports use when none is given.
+    pub operating_currency: Option<Currency>,
+    pub fiscal_year_start: FiscalYearStart,
+    /// The largest difference still considered equal when comparing amounts, only set by the
+    /// `default_tolerance` option.
+    pub default_tolerance: Option<BigRational>,
+    /// Number of decimal places used when displaying amounts.
+    pub display_precision: u32,
+    /// How many days apart transactions can be and still be reported as duplicates.
+    pub duplicate_window: u32,
+    /// Whether ops written out of date order are errors instead of warnings.
+    pub strict_date_order: bool,
+    /// Validators turned off or given another severity, by name.
+    pub rules: BTreeMap<String, Option<Severity>>,
+    /// Allowed second-level names, by root account.
+    pub account_categories: BTreeMap<String, Vec<String>>,
+    /// Patterns account names must match, by root account.
+    pub account_patterns: BTreeMap<String, String>,
+    /// The most parts an account name can have, counting its root.
+    pub max_account_depth: Option<u32>,
+}
+
+impl Default for LedgerOptions {
+    fn default() -> Self {
+        Self {
+            operating_currency: None,
+            fiscal_year_start: FiscalYearStart::default(),
+            default_tolerance: None,
+            display_precision: 2,
+            duplicate_window: 0,
+            strict_date_order: false,
+            rules: BTreeMap::new(),
+            account_categories: BTreeMap::new(),
+            account_patterns: BTreeMap::new(),
+            max_account_depth: None,
+        }
+    }
+}
+
+impl LedgerOptions {
+    pub fn set(&mut self, option: LedgerOption) {
+        match option {
+            LedgerOption::OperatingCurrency(c) => self.operating_currency = Some(c),
+            LedgerOption::FiscalYearStart(f) => self.fiscal_year_start = f,
+            LedgerOption::DefaultTolerance(t) => self.default_tolerance = Some(t),
+            LedgerOption::DisplayPrecision(p) => self.display_precision = p,
+            LedgerOption::DuplicateWindow(d) => self.duplicate_window = d,
+            LedgerOption::StrictDateOrder(s) => self.strict_date_order = s,
+            LedgerOption::Rule(name, severity) => {
+                self.rules.insert(name, severity);
+            }
+            LedgerOption::AccountCategories(root, categories) => {
+                self.account_categories.insert(root, categories);
+            }
+            LedgerOption::AccountPattern(root, pattern) => {
+                self.account_patterns.insert(root, pattern);
+            }
+            LedgerOption::MaxAccountDepth(d) => self.max_account_depth = Some(d),
+        }
+    }
+
+    /// Whether two amounts are equal within the ledger's tolerance, or when rounded to the
+    /// display precision if it has none.
+    pub fn equals(&self, a: f64, b: f64) -> bool {
+        match &self.default_tolerance {
+            Some(tolerance) => (a - b).abs() <= tolerance.to_f64().unwrap_or(0.0) + f64::EPSILON,
+            None => {
+                let factor = 10f64.powi(self.display_precision as i32);
+                (a * factor).round() == (b * factor).round()
+            }
+        }
+    }
+
+    /// Whether an exact difference between amounts is too large to ignore, which is any
+    /// difference at all unless the ledger sets a tolerance.
+    pub fn exceeds_tolerance(&self, difference: &BigRational) -> bool {
+        match &self.default_tolerance {
+            Some(tolerance) => difference.abs() > *tolerance,
+            None => !difference.is_zero(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(
+            LedgerOption::parse("operating_currency", "BRL"),
+            Ok(LedgerOption::OperatingCurrency("BRL".into()))
+        );
+        assert_eq!(
+            LedgerOption::parse("fiscal_year_start", "04-01"),
+            Ok(LedgerOption::FiscalYearStart(FiscalYearStart {
+                month: 4,
+                day: 1
+            }))
+        );
+        assert_eq!(
+            LedgerOption::parse("display_precision", "4"),
+            Ok(LedgerOption::DisplayPrecision(4))
+        );
+
+        assert!(LedgerOption::parse("fiscal_year_start", "13-01").is_err());
+        assert!(LedgerOption::parse("default_tolerance", "lots").is_err());
+        assert_eq!(
+            LedgerOption::parse("rule.missing_document", "off"),
+            Ok(LedgerOption::Rule("missing_document".to_string(), None))
+        );
+        assert_eq!(
+            LedgerOption::parse("rule.missing_document", "warning"),
+            Ok(LedgerOption::Rule(
+                "missing_document".to_string(),
+                Some(Severity::Warning)
+            ))
+        );
+
+        assert!(LedgerOption::parse("unknown", "value").is_err());
+        assert!(LedgerOption::parse("rule.missing_document", "maybe").is_err());
+        assert!(LedgerOption::parse("rule.", "off").is_err());
+
+        assert_eq!(
+            LedgerOption::parse("account_categories.expenses", "food  home"),
+            Ok(LedgerOption::AccountCategories(
+                "expenses".to_string(),
+                vec!["food".to_string(), "home".to_string()]
+            ))
+        );
+        assert!(LedgerOption::parse("account_categories.costs", "food").is_err());
+        assert!(LedgerOption::parse("account_pattern.assets", "^[a-z]+$").is_ok());
+        assert!(LedgerOption::parse("account_pattern.assets", "[a-z").is_err());
+    }
+
+    #[test]
+    fn test_tolerance() {
+        let mut options = LedgerOptions::default();
+        let half_cent = BigRational::new(1.into(), 200.into());
+
+        assert!(options.exceeds_tolerance(&half_cent));
+        assert!(!options.exceeds_tolerance(&BigRational::zero()));
+        assert!(options.equals(10.004, 10.0));
+        assert!(!options.equals(10.006, 10.0));
+
+        options.set(LedgerOption::DefaultTolerance(BigRational::new(
+            1.into(),
+            100.into(),
+        )));
+
+        assert!(!options.exceeds_tolerance(&-half_cent));
+        assert!(options.equals(10.006, 10.0));
+        assert!(!options.equals(10.02, 10.0));
+    }
+
+    #[test]
+    fn test_fiscal_year() {
+        let start = FiscalYearStart { month: 4, day: 1 };
+
+        assert_eq!(
+            start.year_of(NaiveDate::from_ymd(2021, 3, 31)),
+            NaiveDate::from_ymd(2020, 4, 1)
+        );
+        assert_eq!(
+            start.year_of(NaiveDate::from_ymd(2021, 4, 1)),
+            NaiveDate::from_ymd(2021, 4, 1)
+        );
+    }
+}
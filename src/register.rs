@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use polars::prelude::*;
+
+use crate::{
+    account::{is_same_or_child, Account},
+    ledger::Ledger,
+    syntax::Span,
+    LedgerContext,
+};
+
+#[derive(Debug, Clone)]
+pub struct AccountNote {
+    pub date: NaiveDate,
+    pub account: Account,
+    pub text: String,
+    pub span: Span,
+}
+
+impl AccountNote {
+    pub fn new(date: NaiveDate, account: Account, text: String, span: Span) -> Self {
+        Self {
+            date,
+            account,
+            text,
+            span,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountDocument {
+    pub date: NaiveDate,
+    pub account: Account,
+    /// The path as written in the ledger, relative to the ledger file.
+    pub path: String,
+    pub span: Span,
+}
+
+impl AccountDocument {
+    pub fn new(date: NaiveDate, account: Account, path: String, span: Span) -> Self {
+        Self {
+            date,
+            account,
+            path,
+            span,
+        }
+    }
+}
+
+struct RegisterEntry {
+    date: NaiveDate,
+    kind: &'static str,
+    account: String,
+    historical_account: String,
+    description: String,
+    amount: Option<(f64, String)>,
+}
+
+/// Lists every movement, note and document for `account` and its children in date order,
+/// along with the running balance of each currency.
+///
+/// Entries carry both the canonical account name and the name the account had at the time.
+pub fn register(ledger: &Ledger, context: &LedgerContext, account: &Account) -> Result<DataFrame> {
+    let name = account.to_string();
+    let df = ledger.all()?;
+
+    let dates = df.column("ledger.date")?.date()?.as_date_iter();
+    let accounts = df.column("ledger.account_name")?.utf8()?;
+    let historical = df.column("ledger.historical_account_name")?.utf8()?;
+    let descriptions = df.column("ledger.description")?.utf8()?;
+    let currencies = df.column("ledger.currency")?.utf8()?;
+    let amounts = df.column("ledger.signed_amount")?.f64()?;
+
+    let mut entries = dates
+        .zip(accounts)
+        .zip(historical)
+        .zip(descriptions)
+        .zip(currencies)
+        .zip(amounts)
+        .filter_map(|(((((date, acc), hist), desc), cur), amount)| {
+            Some((date?, acc?, hist?, desc?, cur?, amount?))
+        })
+        .filter(|(_, acc, _, _, _, _)| is_same_or_child(acc, &name))
+        .map(|(date, acc, hist, desc, cur, amount)| RegisterEntry {
+            date,
+            kind: "transaction",
+            account: acc.to_string(),
+            historical_account: hist.to_string(),
+            description: desc.to_string(),
+            amount: Some((amount, cur.to_string())),
+        })
+        .collect::<Vec<_>>();
+
+    entries.extend(
+        context
+            .notes
+            .iter()
+            .filter(|n| is_same_or_child(&n.account.to_string(), &name))
+            .map(|n| RegisterEntry {
+                date: n.date,
+                kind: "note",
+                account: n.account.to_string(),
+                historical_account: context.history.name_at(&n.account, n.date).to_string(),
+                description: n.text.clone(),
+                amount: None,
+            }),
+    );
+
+    entries.extend(
+        context
+            .documents
+            .iter()
+            .filter(|d| is_same_or_child(&d.account.to_string(), &name))
+            .map(|d| RegisterEntry {
+                date: d.date,
+                kind: "document",
+                account: d.account.to_string(),
+                historical_account: context.history.name_at(&d.account, d.date).to_string(),
+                description: d.path.clone(),
+                amount: None,
+            }),
+    );
+
+    entries.sort_by_key(|e| e.date);
+
+    let mut running: HashMap<String, f64> = HashMap::new();
+
+    let balances = entries
+        .iter()
+        .map(|e| {
+            e.amount.as_ref().map(|(amount, currency)| {
+                let balance = running.entry(currency.clone()).or_insert(0.0);
+                *balance += amount;
+                *balance
+            })
+        })
+        .collect::<Vec<_>>();
+
+    DataFrame::new(vec![
+        DateChunked::new_from_naive_date(
+            "register.date",
+            &entries.iter().map(|e| e.date).collect::<Vec<_>>(),
+        )
+        .into_series(),
+        Series::new(
+            "register.kind",
+            entries.iter().map(|e| e.kind).collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "register.account_name",
+            entries
+                .iter()
+                .map(|e| e.account.as_str())
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "register.historical_account_name",
+            entries
+                .iter()
+                .map(|e| e.historical_account.as_str())
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "register.description",
+            entries
+                .iter()
+                .map(|e| e.description.as_str())
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "register.amount",
+            entries
+                .iter()
+                .map(|e| e.amount.as_ref().map(|(a, _)| *a))
+                .collect::<Vec<_>>(),
+        ),
+        Series::new(
+            "register.currency",
+            entries
+                .iter()
+                .map(|e| e.amount.as_ref().map(|(_, c)| c.as_str()))
+                .collect::<Vec<_>>(),
+        ),
+        Series::new("register.balance", balances),
+    ])
+}
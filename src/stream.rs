@@ -0,0 +1,753 @@
+//! Verification of ledgers too large to keep in memory, reading them one op at a time.
+//!
+//! Instead of building a [`Ledger`](crate::ledger::Ledger), running totals are kept for each
+//! account and currency, and for each `balance` directive, so memory grows with the number of
+//! accounts and directives instead of the number of movements. The input is read twice: first for
+//! the ops that apply to the whole ledger no matter where they are written (`alias`, `rename`,
+//! `open` and `option`) and for the dates of `balance` directives, then for everything else. The
+//! problems found are the same as the ones found by the built-in validators of
+//! [`ValidationRunner`](crate::validate::ValidationRunner), which ledgers configure with the same
+//! `rule.*` options. Duplicate detection and validators registered by library users need every
+//! transaction at once, and so do `assert` ops, whose queries can look at any account and period,
+//! so they don't run here.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::{self, BufRead, BufReader},
+    path::Path,
+};
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use num::{BigRational, ToPrimitive, Zero};
+
+use crate::{
+    account::Rename,
+    activity::unused,
+    chart::{find_opening, AccountOpening},
+    diagnostic::{Diagnostic, Diagnostics, Excerpt},
+    money::{Movement, MovementKind},
+    register::AccountDocument,
+    syntax::{
+        cst::{tokenize, Line, SyntaxKind},
+        parse_op, Aliases, Op, Span, Spanned,
+    },
+    validate::{
+        check_date_order, check_future_date, check_movement_count, check_sides, check_zero_amount,
+        configure, into_diagnostics, unknown_rules, AbnormalBalances, AccountBalances,
+        AccountNames, AccountNaming, AccountUse, AccountUses, AllowedCurrencies, BalanceAssertions,
+        DailyBalances, DateOrder, DocumentsExist, FutureDates, LedgerBalance, OneSidedTransactions,
+        SingleMovements, TransactionBalance, UnopenedAccounts, UnusedAccounts, ValidationRunner,
+        ValidationTrace, Validator, ZeroAmounts,
+    },
+    BalanceVerification, LedgerContext,
+};
+
+/// Reads the entries of a ledger one at a time, grouped like the ones of a
+/// [`SyntaxTree`](crate::syntax::cst::SyntaxTree), along with the offset where each starts.
+pub(crate) struct Entries<R> {
+    reader: R,
+    /// Characters read so far.
+    offset: usize,
+    current: Option<(String, usize)>,
+    /// Blank and comment lines after `current`, which belong to it like the trailing trivia of a
+    /// token does.
+    pending: String,
+}
+
+impl<R: BufRead> Entries<R> {
+    pub(crate) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            offset: 0,
+            current: None,
+            pending: String::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Entries<R> {
+    type Item = io::Result<(String, usize)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+
+            match self.reader.read_line(&mut line) {
+                Ok(0) => {
+                    return self.current.take().map(|(mut text, offset)| {
+                        text.push_str(&self.pending);
+                        self.pending.clear();
+                        Ok((text, offset))
+                    })
+                }
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+
+            // Triple quoted strings span lines, so they are read until they are closed.
+            while is_open_string(&line) {
+                match self.reader.read_line(&mut line) {
+                    Ok(0) => break,
+                    Ok(_) => {}
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+
+            let start = self.offset;
+            self.offset += line.chars().count();
+
+            let parsed = Line {
+                tokens: tokenize(&line),
+            };
+
+            match &mut self.current {
+                Some((text, _)) if parsed.is_continuation() => {
+                    text.push_str(&self.pending);
+                    text.push_str(&line);
+                    self.pending.clear();
+                }
+                _ if parsed.content().next().is_none() => self.pending.push_str(&line),
+                _ => {
+                    let pending = std::mem::take(&mut self.pending);
+
+                    if let Some((mut text, offset)) = self.current.replace((line, start)) {
+                        text.push_str(&pending);
+                        return Some(Ok((text, offset)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether `line` ends inside a triple quoted string.
+fn is_open_string(line: &str) -> bool {
+    tokenize(line)
+        .last()
+        .is_some_and(|t| t.kind == SyntaxKind::Unknown && t.text.starts_with("\"\"\""))
+}
+
+/// The keyword of the op written in `text`, like `transaction`.
+pub(crate) fn keyword(text: &str) -> Option<String> {
+    tokenize(text)
+        .into_iter()
+        .find(|t| t.kind == SyntaxKind::Identifier)
+        .map(|t| t.text)
+}
+
+/// Whether `text` is an op read in the first pass, either because it applies to the whole
+/// ledger or because movements are summed up to its date.
+fn is_global(text: &str) -> bool {
+    matches!(
+        keyword(text).as_deref(),
+        Some("alias" | "rename" | "open" | "option" | "balance")
+    )
+}
+
+#[derive(Default)]
+struct Verifier {
+    aliases: Aliases,
+    context: LedgerContext,
+    /// The dates of the `balance` directives of each account and currency, in order, each with
+    /// the sum of the signed amounts written after the one before it, up to its own date.
+    totals: HashMap<(String, String), Vec<(NaiveDate, f64)>>,
+    balances: DailyBalances,
+    accounts: AccountBalances,
+    /// Every account with a movement.
+    used: BTreeSet<String>,
+    uses: AccountUses,
+    names: AccountNames,
+    syntax_errors: Vec<Diagnostic>,
+    unbalanced: Vec<ValidationTrace>,
+    currencies: Vec<ValidationTrace>,
+    documents: Vec<ValidationTrace>,
+    /// The last op with a date, to check the next one against.
+    previous: Option<Spanned<NaiveDate>>,
+    out_of_order: Vec<ValidationTrace>,
+    future_dates: FutureDates,
+    future: Vec<ValidationTrace>,
+    single: Vec<ValidationTrace>,
+    zeros: Vec<ValidationTrace>,
+    one_sided: Vec<ValidationTrace>,
+}
+
+impl Verifier {
+    /// Collects an op that applies to the whole ledger. Syntax errors are left for [`Self::check`].
+    fn prepare(&mut self, filename: &Path, (text, offset): (String, usize)) {
+        if !is_global(&text) {
+            return;
+        }
+
+        match parse_op(filename, &text, offset, &Aliases::new()) {
+            Ok((Op::Alias((name, _), (account, _)), _)) => {
+                self.aliases.insert(name, account);
+            }
+            Ok((Op::Rename((date, _), (from, _), (to, _)), span)) => {
+                self.context
+                    .history
+                    .renames
+                    .push(Rename::new(date, from, to, span));
+            }
+            Ok((Op::Open((date, _), (account, _), currencies, metadata), span)) => {
+                self.context.opens.push(AccountOpening::new(
+                    date,
+                    account,
+                    currencies.into_iter().map(|(c, _)| c).collect(),
+                    metadata.into_iter().map(|(m, _)| m).collect(),
+                    span,
+                ));
+            }
+            Ok((Op::Option((option, _)), span)) => self.context.set_option(option, span),
+            Ok((Op::Balance((date, _), (account, _), (amount, amount_span)), span)) => {
+                self.context
+                    .balance_verifications
+                    .push(BalanceVerification::new(
+                        account,
+                        date,
+                        amount,
+                        span,
+                        amount_span,
+                    ));
+            }
+            _ => {}
+        }
+    }
+
+    /// Renames apply to every op, so accounts are only resolved once all of them are known.
+    fn resolve_accounts(&mut self) {
+        for opening in self.context.opens.iter_mut() {
+            opening.account = self.context.history.canonical(&opening.account);
+        }
+
+        for verification in self.context.balance_verifications.iter_mut() {
+            verification.account = self.context.history.canonical(&verification.account);
+
+            self.totals
+                .entry((
+                    verification.account.to_string(),
+                    verification.amount.currency(),
+                ))
+                .or_default()
+                .push((verification.date, 0.0));
+        }
+
+        for dates in self.totals.values_mut() {
+            dates.sort_by_key(|(date, _)| *date);
+            dates.dedup_by_key(|(date, _)| *date);
+        }
+    }
+
+    fn check(&mut self, filename: &Path, (text, offset): (String, usize)) {
+        let (op, span) = match parse_op(filename, &text, offset, &self.aliases) {
+            Ok(op) => op,
+            Err(Diagnostics(diagnostics)) => return self.syntax_errors.extend(diagnostics),
+        };
+
+        if let Some(date) = op.date() {
+            let current = (date, span.clone());
+
+            if let Some(previous) = self.previous.replace(current.clone()) {
+                self.out_of_order
+                    .extend(check_date_order(&previous, &current));
+            }
+
+            self.future
+                .extend(check_future_date(&current, self.future_dates.today));
+        }
+
+        match op {
+            Op::Document((date, _), (account, _), (path, _)) => {
+                let root = filename.parent().unwrap_or_else(|| Path::new("."));
+                let document = AccountDocument::new(
+                    date,
+                    self.context.history.canonical(&account),
+                    path,
+                    span,
+                );
+
+                if !root.join(&document.path).exists() {
+                    self.documents
+                        .push(ValidationTrace::missing_document(&document));
+                }
+            }
+            Op::Transaction((date, _), _, (movements, _), _) => {
+                self.check_transaction(date, &span, movements)
+            }
+            _ => {}
+        }
+    }
+
+    fn check_transaction(&mut self, date: NaiveDate, op: &Span, movements: Vec<Spanned<Movement>>) {
+        let mut sum = BigRational::zero();
+        let start = movements.iter().map(|(_, s)| s.start).min();
+        let end = movements.iter().map(|(_, s)| s.end).max();
+
+        let spans = movements.iter().map(|(_, s)| s.clone()).collect::<Vec<_>>();
+        self.single.extend(check_movement_count(&spans));
+
+        let sides = movements
+            .iter()
+            .map(|(movement, span)| (movement.0, span.clone()))
+            .collect::<Vec<_>>();
+        self.one_sided.extend(check_sides(&sides));
+
+        self.zeros.extend(
+            movements
+                .iter()
+                .filter_map(|(movement, span)| check_zero_amount(&movement.1.amount, span)),
+        );
+
+        self.balances.add_transaction(
+            date,
+            movements.iter().map(|(movement, span)| {
+                let amount = movement.1.amount.clone();
+                let signed = if movement.is_credit() {
+                    amount
+                } else {
+                    -amount
+                };
+
+                (movement.1.currency(), signed, span.clone())
+            }),
+        );
+
+        for (movement, span) in movements.iter() {
+            let account = self.context.history.canonical(&movement.2);
+            let name = account.to_string();
+            let currency = movement.1.currency.clone();
+            let amount = movement.1.amount.to_f64().unwrap_or(f64::NAN);
+
+            self.accounts.add_movement(
+                &account,
+                currency.0.clone(),
+                date,
+                movement.0,
+                movement.1.amount.clone(),
+                span.clone(),
+            );
+
+            if movement.0 == MovementKind::Credit {
+                sum += &movement.1.amount;
+            } else {
+                sum -= &movement.1.amount;
+            }
+
+            // Movements after the last directive are never checked, so they are not kept.
+            if let Some(dates) = self.totals.get_mut(&(name.clone(), currency.0.clone())) {
+                let next = dates.partition_point(|(d, _)| *d < date);
+
+                if let Some((_, sum)) = dates.get_mut(next) {
+                    *sum += amount * account.signed_factor(movement.0) as f64;
+                }
+            }
+
+            self.used.insert(name.clone());
+            self.uses.add(
+                name.clone(),
+                AccountUse {
+                    date,
+                    name: self.context.history.name_at(&account, date).to_string(),
+                    span: span.clone(),
+                    op: op.clone(),
+                },
+            );
+            self.names.add(name.clone(), span);
+
+            if let Some(opening) = find_opening(&self.context.opens, &name) {
+                if !opening.allows(&currency) {
+                    self.currencies.push(ValidationTrace::currency_not_allowed(
+                        &name,
+                        currency,
+                        Some(span.clone()),
+                        opening,
+                    ));
+                }
+            }
+        }
+
+        if self.context.options.exceeds_tolerance(&sum) {
+            self.unbalanced
+                .push(ValidationTrace::unbalanced_transaction(
+                    sum.to_f64().unwrap_or(f64::NAN),
+                    start.zip(end).map(|(start, end)| start..end - 1),
+                    &movements,
+                ));
+        }
+    }
+
+    fn finish(mut self, filename: &Path) -> Vec<Diagnostic> {
+        let mut diagnostics = self.syntax_errors;
+
+        for opening in self.context.opens.iter() {
+            self.names.add(opening.account.to_string(), &opening.span);
+        }
+
+        let options = &self.context.options;
+        let ledger = self.balances.traces(options);
+        let accounts = self.accounts.traces(&self.context.opens, options);
+        let unused = unused(&self.context.opens, &self.used)
+            .into_iter()
+            .map(ValidationTrace::unused_account)
+            .collect();
+        let names = self.names.traces(options);
+        let unopened = self.uses.traces(&self.context.opens);
+
+        for (validator, traces) in [
+            (&LedgerBalance as &dyn Validator, ledger),
+            (&TransactionBalance, self.unbalanced),
+            (&AllowedCurrencies, self.currencies),
+            (&DocumentsExist, self.documents),
+            (&DateOrder, self.out_of_order),
+            (&self.future_dates, self.future),
+            (&AbnormalBalances, accounts),
+            (&UnusedAccounts, unused),
+            (&UnopenedAccounts, unopened),
+            (&AccountNaming, names),
+            (&SingleMovements, self.single),
+            (&ZeroAmounts, self.zeros),
+            (&OneSidedTransactions, self.one_sided),
+        ] {
+            let found = into_diagnostics(traces, validator.code(), filename);
+            diagnostics.extend(configure(validator, found, options));
+        }
+
+        for dates in self.totals.values_mut() {
+            let mut running = 0.0;
+
+            for (_, sum) in dates.iter_mut() {
+                running += *sum;
+                *sum = running;
+            }
+        }
+
+        let balances = self
+            .context
+            .balance_verifications
+            .iter()
+            .filter_map(|verification| {
+                let key = (
+                    verification.account.to_string(),
+                    verification.amount.currency(),
+                );
+                let sum = self
+                    .totals
+                    .get(&key)
+                    .and_then(|dates| {
+                        let i = dates.binary_search_by_key(&verification.date, |(date, _)| *date);
+                        i.ok().map(|i| dates[i].1)
+                    })
+                    .unwrap_or(0.0);
+
+                verification.check(filename, sum, options)
+            })
+            .collect();
+
+        diagnostics.extend(configure(&BalanceAssertions, balances, options));
+
+        // Rules of validators that don't run here are still known, since the same ledger can be
+        // verified in memory.
+        let runner = ValidationRunner::default();
+        diagnostics.extend(unknown_rules(filename, runner.validators(), &self.context));
+
+        diagnostics
+    }
+}
+
+/// Verifies a ledger read from `first` and then again from `second`, which must both read the
+/// same input. Ops dated after `today` are reported.
+pub fn verify<R: BufRead>(
+    filename: &Path,
+    first: R,
+    second: R,
+    today: NaiveDate,
+) -> Result<Vec<Diagnostic>> {
+    let mut verifier = Verifier {
+        future_dates: FutureDates::new(today),
+        ..Verifier::default()
+    };
+
+    for entry in Entries::new(first) {
+        verifier.prepare(filename, entry?);
+    }
+
+    verifier.resolve_accounts();
+
+    for entry in Entries::new(second) {
+        verifier.check(filename, entry?);
+    }
+
+    Ok(verifier.finish(filename))
+}
+
+/// Verifies the ledger at `path`, reading it twice.
+pub fn verify_file(path: &Path, today: NaiveDate) -> Result<Vec<Diagnostic>> {
+    verify(
+        path,
+        BufReader::new(File::open(path)?),
+        BufReader::new(File::open(path)?),
+        today,
+    )
+}
+
+/// Reads the lines `diagnostics` point at, and only those, so they can be shown without
+/// keeping the whole ledger in memory.
+pub fn excerpt<R: BufRead>(mut reader: R, diagnostics: &[Diagnostic]) -> io::Result<Excerpt> {
+    let mut spans = diagnostics
+        .iter()
+        .flat_map(|d| d.span.iter().chain(d.labels.iter().map(|l| &l.span)))
+        // Empty spans still point at the line they are in.
+        .map(|s| s.start..s.end.max(s.start + 1))
+        .collect::<Vec<_>>();
+    spans.sort_by_key(|s| s.start);
+
+    let mut spans = spans.into_iter().peekable();
+    let mut active: Vec<Span> = vec![];
+    let mut excerpt = Excerpt::default();
+    let (mut line, mut last) = (String::new(), String::new());
+    let (mut offset, mut number) = (0, 1);
+
+    loop {
+        line.clear();
+
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let end = offset + line.chars().count();
+
+        while let Some(span) = spans.next_if(|s| s.start < end) {
+            active.push(span);
+        }
+        active.retain(|s| s.end > offset);
+
+        if !active.is_empty() {
+            excerpt.add_line(offset, number, line.clone());
+        }
+
+        std::mem::swap(&mut line, &mut last);
+        offset = end;
+        number += 1;
+    }
+
+    // Spans at the end of the input, like the ones for ops left unfinished, point past every line.
+    if spans.peek().is_some() && !last.is_empty() {
+        let start = offset - last.chars().count();
+        excerpt.add_line(start, number - 1, last);
+    }
+
+    Ok(excerpt)
+}
+
+/// The lines of the ledger at `path` that `diagnostics` point at.
+pub fn excerpt_file(path: &Path, diagnostics: &[Diagnostic]) -> Result<Excerpt> {
+    Ok(excerpt(BufReader::new(File::open(path)?), diagnostics)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{analysis::Analysis, codes, diagnostic::to_json};
+
+    const INPUT: &str = r#"option "default_tolerance" "0.01"
+2020-01-01 open assets:cash BRL
+2020-01-01 open equity:initial_import
+  source: "bank"
+
+// Aliases and renames apply to ops written before them.
+2020-01-02 transaction "Initial import"
+  > 100 BRL equity:initial_import
+  // cash in hand
+  < 100 BRL wallet
+2020-01-03 transaction "Unbalanced"
+  > 10 BRL equity:initial_import
+  < 9 BRL assets:cash
+2020-01-03 transaction "Dollars"
+  > 10 USD equity:initial_import
+  < 10 USD assets:cash
+2020-01-03 balance assets:cash 109 BRL
+2020-01-04 balanse assets:cash 109 BRL
+2020-01-05 balance assets:money 100 BRL
+2020-01-05 document assets:money "missing.pdf"
+2020-01-05 note assets:money """Moved to the
+"new" bank
+
+on the 6th"""
+alias wallet = assets:cash
+2020-01-06 rename assets:cash assets:money
+2020-01-04 note assets:money "Written late"
+2020-01-04 transaction "Swapped"
+  > 5 BRL expenses:food
+  < 5 BRL equity:initial_import
+2020-01-04 open assets:safe BRL
+option "account_categories.expenses" "home"
+2020-01-04 transaction "Not filled in"
+  < 0 BRL expenses:food
+  < 0 BRL expenses:home
+2020-01-04 transaction "Half"
+  < 0 BRL expenses:home
+option "rule.duplicate_transactions" "off"
+"#;
+
+    fn summary(diagnostics: &[Diagnostic]) -> Vec<(&str, Option<usize>)> {
+        let mut summary = diagnostics
+            .iter()
+            .map(|d| (d.code, d.span.clone().map(|s| s.start)))
+            .collect::<Vec<_>>();
+
+        summary.sort();
+        summary
+    }
+
+    #[test]
+    fn test_entries() -> Result<()> {
+        let entries = Entries::new(INPUT.as_bytes()).collect::<io::Result<Vec<_>>>()?;
+
+        assert_eq!(entries.len(), 20);
+        assert_eq!(
+            entries[2].0,
+            "2020-01-01 open equity:initial_import\n  source: \"bank\"\n\n// Aliases and renames apply to ops written before them.\n"
+        );
+        assert!(entries[3].0.contains("// cash in hand\n"));
+        assert!(entries[10].0.ends_with("on the 6th\"\"\"\n"));
+
+        for (text, offset) in &entries {
+            let start = INPUT.char_indices().nth(*offset).unwrap().0;
+            assert!(INPUT[start..].starts_with(text));
+        }
+        // Nothing after the first entry is left out.
+        assert!(INPUT.ends_with(
+            &entries
+                .into_iter()
+                .map(|(text, _)| text)
+                .collect::<String>()
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_matches_ledger() -> Result<()> {
+        let filename = Path::new("books.hta");
+        let today = NaiveDate::from_ymd(2021, 1, 1);
+        let streamed = verify(filename, INPUT.as_bytes(), INPUT.as_bytes(), today)?;
+        let analysis = Analysis::new(filename, INPUT)?;
+
+        assert_eq!(
+            summary(&streamed).iter().map(|s| s.0).collect::<Vec<_>>(),
+            vec![
+                codes::UNEXPECTED_TOKEN,
+                codes::UNBALANCED_LEDGER,
+                codes::UNBALANCED_TRANSACTION,
+                codes::CURRENCY_NOT_ALLOWED,
+                codes::MISSING_DOCUMENT,
+                codes::OUT_OF_ORDER,
+                codes::ABNORMAL_BALANCE,
+                codes::UNUSED_ACCOUNT,
+                codes::ACCOUNT_NAMING,
+                codes::SINGLE_MOVEMENT,
+                codes::ZERO_AMOUNT,
+                codes::ZERO_AMOUNT,
+                codes::ZERO_AMOUNT,
+                codes::ONE_SIDED_TRANSACTION,
+                codes::UNOPENED_ACCOUNT,
+                codes::UNOPENED_ACCOUNT,
+                codes::UNKNOWN_RULE,
+                codes::BALANCE_MISMATCH,
+            ]
+        );
+        assert_eq!(summary(&streamed), summary(&analysis.diagnostics));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_excerpt_only_keeps_lines_shown() -> Result<()> {
+        let filename = Path::new("books.hta");
+        let today = NaiveDate::from_ymd(2021, 1, 1);
+        // Lines no problem points at are never kept, however many there are.
+        let padded = format!("{}{}", "// Nothing to see here\n".repeat(10_000), INPUT);
+
+        let diagnostics = verify(filename, INPUT.as_bytes(), INPUT.as_bytes(), today)?;
+        let padded_diagnostics = verify(filename, padded.as_bytes(), padded.as_bytes(), today)?;
+
+        let shown = excerpt(INPUT.as_bytes(), &diagnostics)?;
+        let padded_shown = excerpt(padded.as_bytes(), &padded_diagnostics)?;
+
+        assert!(!shown.is_empty());
+        assert!(shown.len() < INPUT.lines().count());
+        assert_eq!(padded_shown.len(), shown.len());
+        assert_eq!(
+            to_json(&padded_diagnostics, &padded_shown),
+            to_json(&padded_diagnostics, padded.as_str())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_balances_in_date_order() -> Result<()> {
+        let input = r#"2020-01-01 open assets:cash BRL
+2020-01-01 open equity:initial_import
+2020-01-10 balance assets:cash 30 BRL
+2020-01-20 balance assets:cash 30 BRL
+2020-01-15 balance assets:cash 31 BRL
+2020-01-05 transaction "Written after its balances"
+  > 30 BRL equity:initial_import
+  < 30 BRL assets:cash
+2020-01-25 transaction "After every balance"
+  > 1 BRL equity:initial_import
+  < 1 BRL assets:cash
+"#;
+        let filename = Path::new("books.hta");
+        let today = NaiveDate::from_ymd(2021, 1, 1);
+        let streamed = verify(filename, input.as_bytes(), input.as_bytes(), today)?;
+        let analysis = Analysis::new(filename, input)?;
+
+        let mismatches = streamed
+            .iter()
+            .filter(|d| d.code == codes::BALANCE_MISMATCH)
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            mismatches,
+            vec!["Balance for `assets:cash` on 2020-01-15 does not match, expected `31 BRL`, found 30.00 BRL"]
+        );
+        assert_eq!(summary(&streamed), summary(&analysis.diagnostics));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_future_dates() -> Result<()> {
+        let today = NaiveDate::from_ymd(2020, 1, 5);
+        let streamed = verify(
+            Path::new("books.hta"),
+            INPUT.as_bytes(),
+            INPUT.as_bytes(),
+            today,
+        )?;
+
+        let future = streamed
+            .iter()
+            .filter(|d| d.code == codes::FUTURE_DATE)
+            .map(|d| {
+                d.span
+                    .clone()
+                    .map(|s| INPUT[s].lines().next().unwrap().to_string())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            future,
+            vec![Some(
+                "2020-01-06 rename assets:cash assets:money".to_string()
+            )]
+        );
+
+        Ok(())
+    }
+}
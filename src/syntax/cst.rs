@@ -0,0 +1,391 @@
+//! A lossless, line-oriented view of a ledger file.
+//!
+//! Unlike the token stream used by the parser, nothing is dropped here: whitespace, comments,
+//! blank lines and the exact text of every number are kept, so that printing the tree gives
+//! back the original input byte for byte. Tools that rewrite ledger files build on this.
+//!
+//! The tokens come from the same lexer as the parser's, so both always agree on where each token
+//! and each entry starts.
+
+use std::fmt::{self, Display};
+
+use chumsky::Parser;
+
+use crate::syntax::{
+    lexer::{lossless_lexer, Lexeme},
+    Span, Token,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    Whitespace,
+    Newline,
+    Comment,
+    Number,
+    Identifier,
+    Currency,
+    String,
+    Separator,
+    Movement,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxToken {
+    pub kind: SyntaxKind,
+    pub text: String,
+    pub span: Span,
+}
+
+impl SyntaxToken {
+    pub fn is_trivia(&self) -> bool {
+        matches!(
+            self.kind,
+            SyntaxKind::Whitespace | SyntaxKind::Newline | SyntaxKind::Comment
+        )
+    }
+}
+
+impl From<&Token> for SyntaxKind {
+    fn from(token: &Token) -> Self {
+        match token {
+            Token::Comment(_) => Self::Comment,
+            Token::Identifier(_) => Self::Identifier,
+            Token::Movement(_) => Self::Movement,
+            Token::String(_) => Self::String,
+            Token::Currency(_) => Self::Currency,
+            Token::Number(_) => Self::Number,
+            Token::Separator(_) => Self::Separator,
+        }
+    }
+}
+
+/// Splits `input` into tokens, keeping all trivia. The tokens are the ones read by
+/// [`crate::syntax::lexer`], with the same spans in characters. Text the lexer skipped, and tokens
+/// it found errors in, like unclosed strings, are kept as [`SyntaxKind::Unknown`].
+pub fn tokenize(input: &str) -> Vec<SyntaxToken> {
+    let chars = input.chars().collect::<Vec<_>>();
+    let (lexemes, errors) = lossless_lexer().parse_recovery(input);
+    let mut tokens = vec![];
+    let mut pos = 0;
+
+    let mut push = |kind, span: Span| {
+        tokens.push(SyntaxToken {
+            kind,
+            text: chars[span.clone()].iter().collect(),
+            span,
+        })
+    };
+
+    for (lexeme, span) in lexemes.unwrap_or_default() {
+        if pos < span.start {
+            push(SyntaxKind::Unknown, pos..span.start);
+        }
+
+        let broken = errors
+            .iter()
+            .any(|e| span.start <= e.span().start && e.span().end <= span.end);
+
+        let kind = match lexeme {
+            _ if broken => SyntaxKind::Unknown,
+            Lexeme::Trivia(kind) => kind,
+            Lexeme::Token(token) => SyntaxKind::from(&token),
+        };
+
+        pos = span.end;
+        push(kind, span);
+    }
+
+    if pos < chars.len() {
+        push(SyntaxKind::Unknown, pos..chars.len());
+    }
+
+    tokens
+}
+
+/// A single line of input, including its indentation, trailing comment and newline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub tokens: Vec<SyntaxToken>,
+}
+
+impl Line {
+    /// Tokens that carry meaning, without whitespace, comments or the newline.
+    pub fn content(&self) -> impl Iterator<Item = &SyntaxToken> {
+        self.tokens.iter().filter(|t| !t.is_trivia())
+    }
+
+    pub fn comment(&self) -> Option<&SyntaxToken> {
+        self.tokens.iter().find(|t| t.kind == SyntaxKind::Comment)
+    }
+
+    pub fn is_blank(&self) -> bool {
+        self.tokens
+            .iter()
+            .all(|t| matches!(t.kind, SyntaxKind::Whitespace | SyntaxKind::Newline))
+    }
+
+    pub fn is_indented(&self) -> bool {
+        self.tokens
+            .first()
+            .is_some_and(|t| t.kind == SyntaxKind::Whitespace)
+    }
+
+    pub fn span(&self) -> Span {
+        match (self.tokens.first(), self.tokens.last()) {
+            (Some(first), Some(last)) => first.span.start..last.span.end,
+            _ => 0..0,
+        }
+    }
+
+    /// Whether this line continues the entry above it, like a movement or a metadata pair.
+    pub(crate) fn is_continuation(&self) -> bool {
+        let content = self.content().collect::<Vec<_>>();
+
+        match content.as_slice() {
+            [] => self.is_indented() && self.comment().is_some(),
+            [first, ..] if first.kind == SyntaxKind::Number => false,
+            [_, ..] if self.is_indented() => true,
+            [first, ..] if first.kind == SyntaxKind::Movement => true,
+            [first, second, third, ..] => {
+                first.kind == SyntaxKind::Identifier
+                    && second.text == ":"
+                    && third.kind == SyntaxKind::String
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.tokens.iter().try_for_each(|t| write!(f, "{}", t.text))
+    }
+}
+
+/// One op with its continuation lines, plus the blank and comment lines right before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub leading: Vec<Line>,
+    pub lines: Vec<Line>,
+}
+
+impl Entry {
+    pub fn span(&self) -> Span {
+        match (self.lines.first(), self.lines.last()) {
+            (Some(first), Some(last)) => first.span().start..last.span().end,
+            _ => 0..0,
+        }
+    }
+}
+
+impl Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.leading
+            .iter()
+            .chain(self.lines.iter())
+            .try_for_each(|l| write!(f, "{}", l))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxTree {
+    pub entries: Vec<Entry>,
+    /// Blank and comment lines after the last entry.
+    pub trailing: Vec<Line>,
+}
+
+impl SyntaxTree {
+    pub fn parse(input: &str) -> Self {
+        let mut lines = vec![];
+        let mut current = vec![];
+
+        for token in tokenize(input) {
+            let is_newline = token.kind == SyntaxKind::Newline;
+            current.push(token);
+
+            if is_newline {
+                lines.push(Line {
+                    tokens: std::mem::take(&mut current),
+                });
+            }
+        }
+
+        if !current.is_empty() {
+            lines.push(Line { tokens: current });
+        }
+
+        let mut entries: Vec<Entry> = vec![];
+        let mut pending = vec![];
+
+        for line in lines {
+            let last = entries.last_mut().filter(|_| line.is_continuation());
+
+            if let Some(entry) = last {
+                entry.lines.append(&mut pending);
+                entry.lines.push(line);
+            } else if line.content().next().is_none() {
+                pending.push(line);
+            } else {
+                entries.push(Entry {
+                    leading: std::mem::take(&mut pending),
+                    lines: vec![line],
+                });
+            }
+        }
+
+        Self {
+            entries,
+            trailing: pending,
+        }
+    }
+}
+
+impl Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.entries.iter().try_for_each(|e| write!(f, "{}", e))?;
+        self.trailing.iter().try_for_each(|l| write!(f, "{}", l))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::{collection::vec, prelude::*};
+
+    use crate::syntax::lexer;
+
+    const INPUT: &str = "// Opening balances\n\
+        2020-01-01 open assets:cash_account BRL\n\
+        \n\
+        2020-01-02 transaction \"Buy some books\" // on sale\n\
+        \x20 > 100.00 BRL liabilities:credit_card\n\
+        \x20 // paid later\n\
+        \x20 < 100.00 BRL expenses:stuff\n\
+        \n\
+        // the end";
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(SyntaxTree::parse(INPUT).to_string(), INPUT);
+        assert_eq!(SyntaxTree::parse("").to_string(), "");
+        assert_eq!(SyntaxTree::parse("\n\n  \n").to_string(), "\n\n  \n");
+    }
+
+    #[test]
+    fn test_entries() {
+        let tree = SyntaxTree::parse(INPUT);
+
+        assert_eq!(tree.entries.len(), 2);
+        assert_eq!(tree.entries[0].leading.len(), 1);
+        assert_eq!(tree.entries[1].leading.len(), 1);
+        assert_eq!(tree.entries[1].lines.len(), 4);
+        assert_eq!(tree.trailing.len(), 2);
+    }
+
+    #[test]
+    fn test_strings() {
+        let kinds = |input: &str| {
+            tokenize(input)
+                .into_iter()
+                .filter(|t| !t.is_trivia())
+                .map(|t| (t.kind, t.text))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            kinds(r#""say \"hi\"" "a\\""#),
+            vec![
+                (SyntaxKind::String, r#""say \"hi\"""#.to_string()),
+                (SyntaxKind::String, r#""a\\""#.to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds("\"\"\"first\n\"second\"\n\"\"\" BRL"),
+            vec![
+                (
+                    SyntaxKind::String,
+                    "\"\"\"first\n\"second\"\n\"\"\"".to_string()
+                ),
+                (SyntaxKind::Currency, "BRL".to_string()),
+            ]
+        );
+        assert_eq!(
+            kinds("\"open\nBRL"),
+            vec![
+                (SyntaxKind::Unknown, "\"open".to_string()),
+                (SyntaxKind::Currency, "BRL".to_string()),
+            ]
+        );
+        assert_eq!(kinds("\"\"\"open\nBRL")[0].0, SyntaxKind::Unknown);
+
+        let input = "2020-01-01 note assets:cash \"\"\"\n  one\n\ntwo\n\"\"\"\n";
+        let tree = SyntaxTree::parse(input);
+        assert_eq!(tree.entries.len(), 1);
+        assert_eq!(tree.to_string(), input);
+    }
+
+    #[test]
+    fn test_keeps_number_text() {
+        let numbers = tokenize("> 0100.50 BRL")
+            .into_iter()
+            .filter(|t| t.kind == SyntaxKind::Number)
+            .map(|t| t.text)
+            .collect::<Vec<_>>();
+
+        assert_eq!(numbers, vec!["0100.50"]);
+    }
+
+    fn ledger_text() -> impl Strategy<Value = String> {
+        let piece = prop_oneof![
+            Just("2020-01-01 "),
+            Just("open assets:cash_account BRL"),
+            Just("  > 100.50 BRL expenses:food"),
+            Just("< 0.1 USD"),
+            Just("ABCDEFG"),
+            Just("\"say \\\"hi\\\"\""),
+            Just("\"bad \\q"),
+            Just("\"\"\""),
+            Just("// comment"),
+            Just("assert sum(expenses:*, 2020-01) <= 10 BRL"),
+            Just("\n"),
+            Just("\t"),
+        ]
+        .prop_map(str::to_string);
+
+        vec(
+            piece.boxed().prop_union("[ -~\n\t\u{e9}]{1,4}".boxed()),
+            0..24,
+        )
+        .prop_map(|pieces| pieces.concat())
+    }
+
+    proptest! {
+        #[test]
+        fn test_tokenize_agrees_with_lexer(input in ledger_text()) {
+            let tokens = tokenize(&input);
+            let (lexed, errors) = lexer().parse_recovery(input.as_str());
+            let broken = |span: &Span| {
+                errors
+                    .iter()
+                    .any(|e| span.start <= e.span().start && e.span().end <= span.end)
+            };
+
+            let lexed = lexed
+                .map(|(tokens, _)| tokens)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|(_, span)| !broken(span))
+                .map(|(token, span)| (SyntaxKind::from(&token), span))
+                .collect::<Vec<_>>();
+            let tokenized = tokens
+                .iter()
+                .filter(|t| !t.is_trivia() && t.kind != SyntaxKind::Unknown)
+                .map(|t| (t.kind, t.span.clone()))
+                .collect::<Vec<_>>();
+
+            prop_assert_eq!(tokenized, lexed);
+            prop_assert_eq!(tokens.iter().map(|t| t.text.as_str()).collect::<String>(), input);
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Canonical formatting for ledger files, built on top of the lossless [`SyntaxTree`].
+//!
+//! The formatter normalizes dates and indentation, aligns the amounts and accounts of every
+//! movement inside an entry and sorts entries that happen on the same day. Comments and the
+//! exact text of numbers are kept as written, and formatting is idempotent.
+
+use chrono::NaiveDate;
+
+use super::cst::{Entry, Line, SyntaxKind, SyntaxToken, SyntaxTree};
+
+const INDENT: &str = "  ";
+
+/// Tokens written next to each other, without whitespace between them.
+type Chunk<'a> = Vec<&'a SyntaxToken>;
+
+fn chunks(line: &Line) -> Vec<Chunk<'_>> {
+    let mut chunks: Vec<Chunk> = vec![];
+    let mut separated = true;
+
+    for token in line.tokens.iter() {
+        if token.is_trivia() {
+            separated = true;
+            continue;
+        }
+
+        match chunks.last_mut() {
+            Some(chunk) if !separated => chunk.push(token),
+            _ => chunks.push(vec![token]),
+        }
+
+        separated = false;
+    }
+
+    chunks
+}
+
+fn chunk_text(chunk: &[&SyntaxToken]) -> String {
+    chunk.iter().map(|t| t.text.as_str()).collect()
+}
+
+fn chunk_date(chunk: &[&SyntaxToken]) -> Option<NaiveDate> {
+    match chunk {
+        [y, s1, m, s2, d]
+            if y.kind == SyntaxKind::Number
+                && m.kind == SyntaxKind::Number
+                && d.kind == SyntaxKind::Number
+                && s1.text == "-"
+                && s2.text == "-" =>
+        {
+            NaiveDate::from_ymd_opt(
+                y.text.parse().ok()?,
+                m.text.parse().ok()?,
+                d.text.parse().ok()?,
+            )
+        }
+        _ => None,
+    }
+}
+
+fn entry_date(entry: &Entry) -> Option<NaiveDate> {
+    let first = entry.lines.first()?;
+    let chunks = chunks(first);
+
+    chunk_date(chunks.first()?)
+}
+
+/// Order of entries within the same day: accounts are opened first and balances asserted last.
+fn entry_priority(entry: &Entry) -> u8 {
+    let keyword = entry
+        .lines
+        .first()
+        .and_then(|l| l.content().find(|t| t.kind == SyntaxKind::Identifier))
+        .map(|t| t.text.as_str());
+
+    match keyword {
+        Some("open") => 0,
+        Some("rename") => 1,
+        Some("transaction") => 2,
+        Some("note") => 3,
+        Some("document") => 4,
+        Some("balance") => 5,
+        _ => 6,
+    }
+}
+
+fn with_comment(text: String, line: &Line) -> String {
+    match line.comment() {
+        Some(comment) if text.trim().is_empty() => format!("{}{}", text, comment.text.trim_end()),
+        Some(comment) => format!("{} {}", text, comment.text.trim_end()),
+        None => text,
+    }
+}
+
+/// A movement line split into its kind, amount, currency and the rest of the line.
+fn movement_parts(line: &Line) -> Option<(String, String, String, String)> {
+    let chunks = chunks(line);
+
+    match chunks.as_slice() {
+        [kind, amount, currency, rest @ ..]
+            if !rest.is_empty()
+                && matches!(kind.as_slice(), [t] if t.kind == SyntaxKind::Movement)
+                && matches!(amount.as_slice(), [t] if t.kind == SyntaxKind::Number)
+                && matches!(currency.as_slice(), [t] if t.kind == SyntaxKind::Currency) =>
+        {
+            Some((
+                chunk_text(kind),
+                chunk_text(amount),
+                chunk_text(currency),
+                rest.iter()
+                    .map(|c| chunk_text(c))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn format_first_line(line: &Line) -> String {
+    let text = chunks(line)
+        .iter()
+        .enumerate()
+        .map(|(i, chunk)| match chunk_date(chunk) {
+            Some(date) if i == 0 => date.format("%Y-%m-%d").to_string(),
+            _ => chunk_text(chunk),
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    with_comment(text, line)
+}
+
+fn format_entry(entry: &Entry, out: &mut Vec<String>) {
+    let lines = entry
+        .lines
+        .iter()
+        .filter(|l| !l.is_blank())
+        .collect::<Vec<_>>();
+
+    let movements = lines.iter().skip(1).filter_map(|l| movement_parts(l));
+    let (amount_width, currency_width) = movements.fold((0, 0), |(a, c), (_, amount, cur, _)| {
+        (a.max(amount.len()), c.max(cur.len()))
+    });
+
+    for (i, line) in lines.into_iter().enumerate() {
+        if i == 0 {
+            out.push(format_first_line(line));
+            continue;
+        }
+
+        let text = match movement_parts(line) {
+            Some((kind, amount, currency, rest)) => format!(
+                "{}{} {:>aw$} {:<cw$} {}",
+                INDENT,
+                kind,
+                amount,
+                currency,
+                rest,
+                aw = amount_width,
+                cw = currency_width
+            ),
+            None => {
+                let content = chunks(line)
+                    .iter()
+                    .map(|c| chunk_text(c))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+
+                format!("{}{}", INDENT, content)
+            }
+        };
+
+        let text = match text.trim_end() {
+            "" => INDENT.to_string(),
+            text => text.to_string(),
+        };
+
+        out.push(with_comment(text, line));
+    }
+}
+
+/// Blank lines collapse into one, and comments are moved to the first column.
+fn format_loose_lines(lines: &[Line], out: &mut Vec<String>) {
+    for line in lines {
+        if line.is_blank() {
+            if out.last().is_some_and(|l| !l.is_empty()) {
+                out.push(String::new());
+            }
+        } else {
+            out.push(with_comment(String::new(), line));
+        }
+    }
+}
+
+/// Sorts entries of the same day by their kind, keeping their relative order otherwise.
+///
+/// Blank lines that separate the day from the entries before it stay at the start of the day.
+fn sort_within_days(entries: &mut [Entry]) {
+    let mut start = 0;
+
+    while start < entries.len() {
+        let date = entry_date(&entries[start]);
+        let mut end = start + 1;
+
+        while date.is_some() && end < entries.len() && entry_date(&entries[end]) == date {
+            end += 1;
+        }
+
+        let blanks = entries[start]
+            .leading
+            .iter()
+            .take_while(|l| l.is_blank())
+            .count();
+        let separator = entries[start].leading.drain(..blanks).collect::<Vec<_>>();
+
+        entries[start..end].sort_by_key(entry_priority);
+        entries[start].leading.splice(..0, separator);
+        start = end;
+    }
+}
+
+pub fn format_tree(tree: &SyntaxTree) -> String {
+    let mut entries = tree.entries.clone();
+    sort_within_days(&mut entries);
+
+    let mut out = vec![];
+
+    for entry in entries.iter() {
+        format_loose_lines(&entry.leading, &mut out);
+        format_entry(entry, &mut out);
+    }
+
+    format_loose_lines(&tree.trailing, &mut out);
+
+    while out.last().is_some_and(|l| l.is_empty()) {
+        out.pop();
+    }
+
+    if out.is_empty() {
+        return String::new();
+    }
+
+    out.join("\n") + "\n"
+}
+
+pub fn format_source(input: &str) -> String {
+    format_tree(&SyntaxTree::parse(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORMATTED: &str = r#"// Opening balances
+2020-01-01 open equity:initial_import BRL
+2020-01-01 open assets:cash_account BRL
+
+2020-01-02 transaction "Move some equity" // one-off
+  > 1300.00 BRL  equity:initial_import
+  <       5 USD  assets:cash_account
+  // change
+  <    95.5 BRLX assets:cash_account
+2020-01-02 balance assets:cash_account 0 BRL
+"#;
+
+    #[test]
+    fn test_formatted_input_is_unchanged() {
+        assert_eq!(format_source(FORMATTED), FORMATTED);
+    }
+
+    #[test]
+    fn test_format() {
+        let input = r#"
+
+// Opening balances
+2020-1-1   open equity:initial_import BRL
+   2020-01-01 open assets:cash_account BRL
+
+
+2020-01-02 balance assets:cash_account   0 BRL
+2020-01-02 transaction "Move some equity"    // one-off
+ > 1300.00 BRL   equity:initial_import
+      < 5 USD assets:cash_account
+    // change
+ < 95.5 BRLX assets:cash_account   "#;
+
+        assert_eq!(format_source(input), FORMATTED);
+        assert_eq!(format_source(&format_source(input)), FORMATTED);
+    }
+}
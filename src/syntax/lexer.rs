@@ -1,9 +1,13 @@
 use chumsky::prelude::*;
 
-use crate::{money::*, syntax::*};
+use crate::{
+    money::*,
+    syntax::{cst::SyntaxKind, *},
+    utils::parse_decimal,
+};
 
 fn separator() -> impl Parser<char, Token, Error = Simple<char>> {
-    one_of(":-".chars()).map(|c| Token::Separator(c))
+    one_of(":-=(),*!".chars()).map(|c| Token::Separator(c))
 }
 
 fn number() -> impl Parser<char, Token, Error = Simple<char>> {
@@ -11,10 +15,11 @@ fn number() -> impl Parser<char, Token, Error = Simple<char>> {
         .chain::<char, _, _>(just('.').chain(text::digits(10)).or_not().flatten())
         .collect::<String>();
 
-    num.try_map(move |number: String, span| match number.parse::<f64>() {
-        Ok(n) => Ok(Token::number(n)),
+    num.try_map(move |number: String, span| match parse_decimal(&number) {
+        Some(n) => Ok(Token::Number(n)),
         _ => Err(Simple::custom(span, "Not a valid number")),
-    }).labelled("number")
+    })
+    .labelled("number")
 }
 
 fn movement() -> impl Parser<char, Token, Error = Simple<char>> {
@@ -24,12 +29,120 @@ fn movement() -> impl Parser<char, Token, Error = Simple<char>> {
         .labelled("movement kind")
 }
 
+/// Replaces the escapes in the raw text of a string, also returning the escapes that are not
+/// valid, with their character range in `raw`.
+fn unescape(raw: &[char]) -> (String, Vec<(Span, String)>) {
+    let mut text = String::new();
+    let mut errors = vec![];
+    let mut pos = 0;
+
+    while pos < raw.len() {
+        if raw[pos] != '\\' {
+            text.push(raw[pos]);
+            pos += 1;
+            continue;
+        }
+
+        let start = pos;
+        pos = (pos + 2).min(raw.len());
+
+        match raw.get(start + 1) {
+            Some('\\') => text.push('\\'),
+            Some('"') => text.push('"'),
+            Some('n') => text.push('\n'),
+            Some('u') => {
+                let digits = match raw.get(pos) {
+                    Some('{') => raw[pos + 1..].iter().position(|c| *c == '}'),
+                    _ => None,
+                };
+                let code = digits
+                    .filter(|n| (1..=6).contains(n))
+                    .map(|n| &raw[pos + 1..pos + 1 + n])
+                    .filter(|d| d.iter().all(char::is_ascii_hexdigit))
+                    .and_then(|d| u32::from_str_radix(&d.iter().collect::<String>(), 16).ok())
+                    .and_then(char::from_u32);
+
+                match code {
+                    Some(c) => {
+                        text.push(c);
+                        pos += digits.unwrap() + 2;
+                    }
+                    None => errors.push((
+                        start..pos,
+                        "Invalid unicode escape, expected up to six hex digits like `\\u{e9}`"
+                            .to_string(),
+                    )),
+                }
+            }
+            Some(c) => errors.push((
+                start..pos,
+                format!("Unknown escape `\\{}`", c.escape_default()),
+            )),
+            None => errors.push((
+                start..pos,
+                "Unfinished escape at the end of the string".to_string(),
+            )),
+        }
+    }
+
+    (text, errors)
+}
+
+/// A string, either in double quotes on a single line or in triple quotes over many. Both
+/// accept the escapes `\"`, `\\`, `\n` and `\u{...}`.
 fn string() -> impl Parser<char, Token, Error = Simple<char>> {
-    just('"')
-        .ignore_then(filter(|c| *c != '"').repeated())
-        .then_ignore(just('"'))
-        .collect::<String>()
-        .map(Token::String)
+    // Escapes are kept as written here, and replaced once the whole string was read.
+    let escape = just('\\').chain(any());
+    let triple = || seq("\"\"\"".chars());
+
+    // A backslash at the end of a line doesn't escape the newline, which ends the string.
+    let single_line_char = just('\\')
+        .chain(filter(|c: &char| *c != '\n'))
+        .or(none_of("\\\"\n".chars()).map(|c| vec![c]));
+    let single_line = just('"')
+        .ignore_then(single_line_char.repeated().flatten())
+        .then(just('"').or_not())
+        .map(|(raw, end)| (raw, end.is_some(), 1));
+
+    // Up to two quotes in a row are part of the text, three end it.
+    let multi_line_char = escape.or(none_of("\\\"".chars()).map(|c| vec![c]));
+    let quotes = just('"')
+        .repeated()
+        .at_least(1)
+        .at_most(2)
+        .chain(multi_line_char.clone());
+
+    let multi_line = triple()
+        .ignore_then(multi_line_char.or(quotes).repeated().flatten())
+        .then(triple().or_not())
+        .map(|(raw, end)| (raw, end.is_some(), 3));
+
+    multi_line
+        .or(single_line)
+        .map(|(raw, closed, quotes)| (unescape(&raw), closed, quotes))
+        .validate(|((text, errors), closed, quotes), span: Span, emit| {
+            let content = span.start + quotes;
+
+            if !closed {
+                emit(Simple::unclosed_delimiter(
+                    span.start..content,
+                    '"',
+                    span.clone(),
+                    '"',
+                    None,
+                ));
+            }
+
+            for (range, message) in errors.iter() {
+                emit(Simple::custom(
+                    content + range.start..content + range.end,
+                    message,
+                ));
+            }
+
+            ((text, errors), closed, quotes)
+        })
+        .map(|((text, _), _, _)| Token::String(text))
 }
 
 fn identifier() -> impl Parser<char, Token, Error = Simple<char>> {
@@ -53,30 +166,54 @@ fn currency() -> impl Parser<char, Token, Error = Simple<char>> {
         .map(Token::currency)
 }
 
-pub fn lexer() -> impl Parser<char, Spanned<Vec<Spanned<Token>>>, Error = Simple<char>> {
+/// A token, or the whitespace, newline or comment around it.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Lexeme {
+    Trivia(SyntaxKind),
+    Token(Token),
+}
+
+/// Reads the whole input, trivia included, so the lexemes cover every character except the ones
+/// that can't start a token, which are skipped with an error.
+pub(crate) fn lossless_lexer() -> impl Parser<char, Vec<Spanned<Lexeme>>, Error = Simple<char>> {
     let token = currency()
         .or(movement())
         .or(string())
         .or(separator())
         .or(number())
         .or(identifier())
-        .recover_with(skip_then_retry_until([]));
-
-    let until_eol = take_until(just('\n'))
-        .map(|(text, _)| text)
-        .collect::<String>();
+        .map(Lexeme::Token);
 
+    let whitespace = filter(|c: &char| c.is_whitespace() && *c != '\n')
+        .repeated()
+        .at_least(1)
+        .to(Lexeme::Trivia(SyntaxKind::Whitespace));
+    let newline = just('\n').to(Lexeme::Trivia(SyntaxKind::Newline));
     let comment = seq("//".chars())
-        .then(until_eol)
-        .padded()
-        .map(|(_, text)| Token::Comment(text));
-
-    token
-        .padded_by(comment.repeated())
-        .padded_by(text::whitespace().ignored().or(just('\n').ignored()))
-        .map_with_span(|tok, span| (tok, span))
+        .then(filter(|c: &char| *c != '\n').repeated())
+        .to(Lexeme::Trivia(SyntaxKind::Comment));
+
+    whitespace
+        .or(newline)
+        .or(comment)
+        .or(token)
+        .recover_with(skip_then_retry_until([]))
+        .map_with_span(|lexeme, span| (lexeme, span))
         .repeated()
-        .map_with_span(|tok, span| (tok, span))
+}
+
+pub fn lexer() -> impl Parser<char, Spanned<Vec<Spanned<Token>>>, Error = Simple<char>> {
+    lossless_lexer()
+        .map(|lexemes| {
+            lexemes
+                .into_iter()
+                .filter_map(|(lexeme, span)| match lexeme {
+                    Lexeme::Token(token) => Some((token, span)),
+                    Lexeme::Trivia(_) => None,
+                })
+                .collect()
+        })
+        .map_with_span(|tokens, span| (tokens, span))
 }
 
 #[cfg(test)]
@@ -279,4 +416,77 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_lexer_string_escapes() -> Result<()> {
+        let parser = lexer();
+
+        assert_eq!(
+            clean_up(
+                parser
+                    .parse(r#""say \"hi\"\n" "C:\\docs" "caf\u{e9}""#)
+                    .unwrap()
+            ),
+            vec![
+                Token::String("say \"hi\"\n".to_string()),
+                Token::String("C:\\docs".to_string()),
+                Token::String("café".to_string()),
+            ]
+        );
+
+        let errors = parser.parse(r#""bad \q" "\u{zz}""#).unwrap_err();
+        assert_eq!(
+            errors.iter().map(|e| e.span()).collect::<Vec<_>>(),
+            vec![5..7, 10..12]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_triple_quoted_string() -> Result<()> {
+        let parser = lexer();
+
+        assert_eq!(
+            clean_up(
+                parser
+                    .parse("\"\"\"first\n\"quoted\" \"\"\n\\\"\"\"\" BRL")
+                    .unwrap()
+            ),
+            vec![
+                Token::String("first\n\"quoted\" \"\"\n\"".to_string()),
+                Token::currency("BRL"),
+            ]
+        );
+
+        assert_eq!(
+            clean_up(parser.parse(r#""""#).unwrap()),
+            vec![Token::String(String::new())]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_unterminated_string() {
+        let (tokens, errors) = lexer().parse_recovery("\"open\nBRL");
+
+        assert_eq!(
+            tokens.map(clean_up),
+            Some(vec![
+                Token::String("open".to_string()),
+                Token::currency("BRL")
+            ])
+        );
+        assert!(matches!(
+            errors[0].reason(),
+            chumsky::error::SimpleReason::Unclosed { span, .. } if *span == (0..1)
+        ));
+
+        let errors = lexer().parse("x \"\"\"never\nclosed").unwrap_err();
+        assert!(matches!(
+            errors[0].reason(),
+            chumsky::error::SimpleReason::Unclosed { span, .. } if *span == (2..5)
+        ));
+    }
 }
@@ -1,32 +1,86 @@
+pub mod cst;
+pub mod format;
 mod lexer;
 mod parser;
+pub mod printer;
 
 pub use lexer::lexer;
-pub use parser::{ parse_file, parse_string };
+pub use parser::{parse_file, parse_op, parse_string, parse_string_recovery};
+pub use printer::print;
+
+use std::collections::HashMap;
 
 use chrono::prelude::*;
 use num::BigRational;
+use serde::{Deserialize, Serialize};
 
-use crate::{account::*, money::*};
+use crate::{
+    account::*, assertion::Assertion, budget::Period, money::*, options::LedgerOption,
+    utils::parse_decimal,
+};
 
 pub type Span = std::ops::Range<usize>;
 pub type Spanned<T> = (T, Span);
 
+/// Short names for accounts, defined with `alias` ops.
+pub type Aliases = HashMap<String, Account>;
+
+/// Free-form `key: "value"` pairs attached to an op, in the order they were written.
+pub type Metadata = Vec<(String, String)>;
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum CleanOp {
-    Open(NaiveDate, Account, Currency),
+    Open(NaiveDate, Account, Vec<Currency>, Metadata),
     Balance(NaiveDate, Account, Money),
-    Transaction(NaiveDate, String, Vec<Movement>),
+    Transaction(NaiveDate, String, Vec<Movement>, Metadata),
+    Budget(Account, Money, Period),
+    Note(NaiveDate, Account, String),
+    Document(NaiveDate, Account, String),
+    Option(LedgerOption),
+    Alias(String, Account),
+    Rename(NaiveDate, Account, Account),
+    Assert(Option<NaiveDate>, Assertion),
 }
 
 impl From<Op> for CleanOp {
     fn from(from: Op) -> Self {
         match from {
-            Op::Open(a, b, c) => Self::Open(a.0, b.0, c.0),
+            Op::Open(a, b, c, m) => Self::Open(
+                a.0,
+                b.0,
+                c.into_iter().map(|(x, _)| x).collect(),
+                m.into_iter().map(|(x, _)| x).collect(),
+            ),
             Op::Balance(a, b, m) => Self::Balance(a.0, b.0, m.0),
-            Op::Transaction(a, b, c) => {
-                Self::Transaction(a.0, b.0, c.0.into_iter().map(|(x, _)| x).collect())
-            }
+            Op::Transaction(a, b, c, m) => Self::Transaction(
+                a.0,
+                b.0,
+                c.0.into_iter().map(|(x, _)| x).collect(),
+                m.into_iter().map(|(x, _)| x).collect(),
+            ),
+            Op::Budget(a, m, p) => Self::Budget(a.0, m.0, p.0),
+            Op::Note(a, b, c) => Self::Note(a.0, b.0, c.0),
+            Op::Document(a, b, c) => Self::Document(a.0, b.0, c.0),
+            Op::Option(o) => Self::Option(o.0),
+            Op::Alias(a, b) => Self::Alias(a.0, b.0),
+            Op::Rename(a, b, c) => Self::Rename(a.0, b.0, c.0),
+            Op::Assert(a, b) => Self::Assert(a.map(|(x, _)| x), b.0),
+        }
+    }
+}
+
+impl Op {
+    /// The date the op is written with, if it has one.
+    pub fn date(&self) -> Option<NaiveDate> {
+        match self {
+            Op::Open((date, _), ..)
+            | Op::Balance((date, _), ..)
+            | Op::Transaction((date, _), ..)
+            | Op::Note((date, _), ..)
+            | Op::Document((date, _), ..)
+            | Op::Rename((date, _), ..) => Some(*date),
+            Op::Assert(date, _) => date.as_ref().map(|(date, _)| *date),
+            Op::Budget(..) | Op::Option(..) | Op::Alias(..) => None,
         }
     }
 }
@@ -37,15 +91,28 @@ impl From<Spanned<Op>> for CleanOp {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Op {
-    Open(Spanned<NaiveDate>, Spanned<Account>, Spanned<Currency>),
+    Open(
+        Spanned<NaiveDate>,
+        Spanned<Account>,
+        Vec<Spanned<Currency>>,
+        Vec<Spanned<(String, String)>>,
+    ),
     Balance(Spanned<NaiveDate>, Spanned<Account>, Spanned<Money>),
     Transaction(
         Spanned<NaiveDate>,
         Spanned<String>,
         Spanned<Vec<Spanned<Movement>>>,
+        Vec<Spanned<(String, String)>>,
     ),
+    Budget(Spanned<Account>, Spanned<Money>, Spanned<Period>),
+    Note(Spanned<NaiveDate>, Spanned<Account>, Spanned<String>),
+    Document(Spanned<NaiveDate>, Spanned<Account>, Spanned<String>),
+    Option(Spanned<LedgerOption>),
+    Alias(Spanned<String>, Spanned<Account>),
+    Rename(Spanned<NaiveDate>, Spanned<Account>, Spanned<Account>),
+    Assert(Option<Spanned<NaiveDate>>, Spanned<Assertion>),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
@@ -53,6 +120,13 @@ pub enum Keyword {
     Open,
     Balance,
     Transaction,
+    Budget,
+    Note,
+    Document,
+    Option,
+    Alias,
+    Rename,
+    Assert,
 }
 
 impl Keyword {
@@ -61,7 +135,14 @@ impl Keyword {
             "open" => Some(Self::Open),
             "balance" => Some(Self::Balance),
             "transaction" => Some(Self::Transaction),
-            _ => None
+            "budget" => Some(Self::Budget),
+            "note" => Some(Self::Note),
+            "document" => Some(Self::Document),
+            "option" => Some(Self::Option),
+            "alias" => Some(Self::Alias),
+            "rename" => Some(Self::Rename),
+            "assert" => Some(Self::Assert),
+            _ => None,
         }
     }
 }
@@ -73,42 +154,50 @@ pub enum Expr {
     Currency(String),
     Amount(BigRational, String),
     Keyword(Keyword),
-    Description(String)
+    Description(String),
+    Period(Period),
 }
 
 impl Expr {
     pub fn get_date(&self) -> Option<NaiveDate> {
         match self {
             Expr::Date(d) => Some(*d),
-            _ => None
+            _ => None,
         }
     }
 
     pub fn get_account(&self) -> Option<Account> {
         match self {
             Expr::Account(a) => Some(a.clone()),
-            _ => None
+            _ => None,
         }
     }
 
     pub fn get_currency(&self) -> Option<String> {
         match self {
             Expr::Currency(c) => Some(c.clone()),
-            _ => None
+            _ => None,
         }
     }
 
     pub fn get_money(&self) -> Option<Money> {
         match self {
             Expr::Amount(a, c) => Some(Money::new(a.clone(), c.clone())),
-            _ => None
+            _ => None,
         }
     }
 
     pub fn get_description(&self) -> Option<String> {
         match self {
             Expr::Description(d) => Some(d.clone()),
-            _ => None
+            _ => None,
+        }
+    }
+
+    pub fn get_period(&self) -> Option<Period> {
+        match self {
+            Expr::Period(p) => Some(*p),
+            _ => None,
         }
     }
 }
@@ -125,8 +214,9 @@ pub enum Token {
 }
 
 impl Token {
+    /// The number written the way `n` is displayed, so `0.1` is exactly one tenth.
     pub fn number(n: f64) -> Self {
-        Self::Number(BigRational::from_float(n).unwrap())
+        Self::Number(parse_decimal(&n.to_string()).unwrap())
     }
 
     pub fn identifier<T: Into<String>>(id: T) -> Self {
@@ -140,70 +230,70 @@ impl Token {
     pub fn is_comment(&self) -> bool {
         match self {
             Token::Comment(_) => true,
-            _ => false
+            _ => false,
         }
     }
 
     pub fn is_identifier(&self) -> bool {
         match self {
             Token::Identifier(_) => true,
-            _ => false
+            _ => false,
         }
     }
 
     pub fn is_movement(&self) -> bool {
         match self {
             Token::Movement(_) => true,
-            _ => false
+            _ => false,
         }
     }
 
     pub fn is_string(&self) -> bool {
         match self {
             Token::String(_) => true,
-            _ => false
+            _ => false,
         }
     }
 
     pub fn is_currency(&self) -> bool {
         match self {
             Token::Currency(_) => true,
-            _ => false
+            _ => false,
         }
     }
 
     pub fn is_number(&self) -> bool {
         match self {
             Token::Number(_) => true,
-            _ => false
+            _ => false,
         }
     }
 
     pub fn is_separator(&self) -> bool {
         match self {
             Token::Separator(_) => true,
-            _ => false
+            _ => false,
         }
     }
 
     pub fn get_number(&self) -> Option<BigRational> {
         match self {
             Token::Number(n) => Some(n.clone()),
-            _ => None
+            _ => None,
         }
     }
 
     pub fn get_string(&self) -> Option<String> {
         match self {
             Token::String(s) => Some(s.clone()),
-            _ => None
+            _ => None,
         }
     }
 
     pub fn get_movement_kind(&self) -> Option<MovementKind> {
         match self {
             Token::Movement(m) => Some(m.clone()),
-            _ => None
+            _ => None,
         }
     }
 }
@@ -215,14 +305,7 @@ impl std::fmt::Display for Token {
             Token::Comment(_) => write!(f, "comment"),
             Token::Identifier(id) => write!(f, "{}", id),
             Token::String(id) => write!(f, "{:?}", id),
-            Token::Movement(mov) => write!(
-                f,
-                "{}",
-                match mov {
-                    MovementKind::Credit => ">",
-                    MovementKind::Debit => "<",
-                }
-            ),
+            Token::Movement(mov) => write!(f, "{}", mov),
             Token::Currency(cur) => write!(f, "{}", cur),
             Token::Number(n) => write!(f, "{}", n),
             Token::Separator(c) => write!(f, "{}", c),
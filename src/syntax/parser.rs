@@ -1,15 +1,19 @@
 use std::{fs, path::Path};
 
-use anyhow::{bail, Result};
-use ariadne::{Color, Fmt, Label, Report, ReportKind, Source};
+use anyhow::Result;
 use chrono::prelude::*;
-use chumsky::prelude::*;
+use chumsky::{prelude::*, Stream};
 use num::{BigRational, ToPrimitive};
 
 use crate::{
     account::*,
-    money::{Movement, MovementKind},
-    syntax::*,
+    assertion::*,
+    budget::Period,
+    codes,
+    diagnostic::{Diagnostic, Diagnostics},
+    money::{Money, Movement, MovementKind},
+    options::LedgerOption,
+    syntax::{cst::SyntaxTree, *},
 };
 
 fn sep(del: char) -> impl Parser<Spanned<Token>, Spanned<Token>, Error = Simple<Spanned<Token>>> {
@@ -110,7 +114,7 @@ fn keyword<T: Into<String>>(
     let val = keyword.into();
 
     filter_map(move |span: Span, token: Spanned<Token>| match token {
-        (Token::Identifier(id), inner) if Keyword::from_str(&id).is_some() => Ok((
+        (Token::Identifier(id), inner) if id == val && Keyword::from_str(&id).is_some() => Ok((
             Expr::Keyword(Keyword::from_str(&id).expect("Failed to get keyword")),
             inner,
         )),
@@ -128,8 +132,10 @@ fn account() -> impl Parser<Spanned<Token>, Spanned<Expr>, Error = Simple<Spanne
         _ => Err(Simple::expected_input_found(span, vec![], Some(token))),
     });
 
+    // Dashes, equals, parentheses and the like belong to dates, aliases and assertions, never to
+    // account names, which the printer always writes back with colons.
     let separator = filter_map(|span: Span, token| match token {
-        t @ (Token::Separator(..), _) => Ok(t),
+        t @ (Token::Separator(':'), _) => Ok(t),
         _ => Err(Simple::expected_input_found(span, vec![], Some(token))),
     });
 
@@ -159,7 +165,8 @@ fn account() -> impl Parser<Spanned<Token>, Spanned<Expr>, Error = Simple<Spanne
         .with_label("account type")),
     });
 
-    kind.then(identifier.repeated().separated_by(separator).flatten())
+    kind.then_ignore(separator)
+        .then(identifier.separated_by(separator).at_least(1))
         .try_map(
             |((kind, sk), parts): ((AccountType, Span), Vec<Spanned<Token>>), _: Span| {
                 let end = parts
@@ -188,6 +195,26 @@ fn account() -> impl Parser<Spanned<Token>, Spanned<Expr>, Error = Simple<Spanne
         )
 }
 
+/// An account name, or a name defined with an `alias` op.
+fn account_ref(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Expr>, Error = Simple<Spanned<Token>>> {
+    let aliases = aliases.clone();
+
+    let alias = filter_map(move |span: Span, token: Spanned<Token>| match token {
+        (Token::Identifier(id), inner) => match aliases.get(&id) {
+            Some(account) => Ok((Expr::Account(account.clone()), inner)),
+            None => Err(Simple::custom(
+                inner,
+                format!("Unknown account or alias `{}`", id),
+            )),
+        },
+        (t, inner) => Err(Simple::expected_input_found(span, vec![], Some((t, inner)))),
+    });
+
+    account().or(alias)
+}
+
 fn amount() -> impl Parser<Spanned<Token>, Spanned<Expr>, Error = Simple<Spanned<Token>>> {
     let number = filter_map(move |span: Span, token: Spanned<Token>| match token {
         (Token::Number(n), inner) => Ok((n, inner)),
@@ -213,10 +240,26 @@ fn currency() -> impl Parser<Spanned<Token>, Spanned<Expr>, Error = Simple<Spann
     })
 }
 
-fn movement() -> impl Parser<Spanned<Token>, Spanned<Movement>, Error = Simple<Spanned<Token>>> {
+fn period() -> impl Parser<Spanned<Token>, Spanned<Expr>, Error = Simple<Spanned<Token>>> {
+    filter_map(move |span: Span, token: Spanned<Token>| match token {
+        (Token::Identifier(id), inner) => match id.parse::<Period>() {
+            Ok(period) => Ok((Expr::Period(period), inner)),
+            Err(_) => Err(Simple::custom(
+                inner,
+                "Expected a budget period: weekly, monthly, quarterly or yearly",
+            )),
+        },
+        (t, inner) => Err(Simple::expected_input_found(span, vec![], Some((t, inner)))),
+    })
+    .labelled("period")
+}
+
+fn movement(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Movement>, Error = Simple<Spanned<Token>>> {
     movement_kind()
         .then(amount())
-        .then(account())
+        .then(account_ref(aliases))
         .map(|(((kind, sk), (amount, _)), (acc, sa))| {
             (
                 Movement(
@@ -231,9 +274,11 @@ fn movement() -> impl Parser<Spanned<Token>, Spanned<Movement>, Error = Simple<S
 }
 
 fn movements(
+    aliases: &Aliases,
 ) -> impl Parser<Spanned<Token>, Spanned<Vec<Spanned<Movement>>>, Error = Simple<Spanned<Token>>> {
-    movement()
+    movement(aliases)
         .repeated()
+        .at_least(1)
         .collect::<Vec<_>>()
         .map(|movs| {
             let start = movs.first().cloned().map(|x| x.1.start()).unwrap();
@@ -244,27 +289,58 @@ fn movements(
         .labelled("movements")
 }
 
+fn metadata(
+) -> impl Parser<Spanned<Token>, Spanned<(String, String)>, Error = Simple<Spanned<Token>>> {
+    let key = filter_map(move |span: Span, token: Spanned<Token>| match token {
+        (Token::Identifier(id), inner) => Ok((id, inner)),
+        (t, inner) => Err(Simple::expected_input_found(span, vec![], Some((t, inner)))),
+    });
+
+    key.then_ignore(sep(':'))
+        .then(string())
+        .map(|((key, sk), (value, sv))| {
+            (
+                (key, value.get_description().unwrap()),
+                sk.start()..sv.end(),
+            )
+        })
+        .labelled("metadata")
+}
+
 fn open_op() -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
     date()
         .then_ignore(keyword("open"))
         .then(account())
-        .then(currency())
-        .map(|(((date, sd), (acc, sa)), (cur, sc))| {
+        .then(currency().repeated())
+        .then(metadata().repeated())
+        .map(|((((date, sd), (acc, sa)), currencies), metadata)| {
+            let end = metadata
+                .last()
+                .map(|(_, s)| s.end())
+                .or_else(|| currencies.last().map(|(_, s)| s.end()))
+                .unwrap_or_else(|| sa.end());
+
             (
                 Op::Open(
                     (date.get_date().unwrap(), sd.clone()),
                     (acc.get_account().unwrap(), sa),
-                    (cur.get_currency().unwrap().into(), sc.clone()),
+                    currencies
+                        .into_iter()
+                        .map(|(cur, sc)| (cur.get_currency().unwrap().into(), sc))
+                        .collect(),
+                    metadata,
                 ),
-                sd.start()..sc.end(),
+                sd.start()..end,
             )
         })
 }
 
-fn balance_op() -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+fn balance_op(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
     date()
         .then_ignore(keyword("balance"))
-        .then(account())
+        .then(account_ref(aliases))
         .then(amount())
         .map(|(((date, sd), (acc, sa)), (amount, sc))| {
             (
@@ -278,120 +354,561 @@ fn balance_op() -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spann
         })
 }
 
-fn transaction_op() -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+fn transaction_op(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
     date()
         .then_ignore(keyword("transaction"))
         .then(string())
-        .then(movements())
-        .map(|(((date, sd), (desc, sde)), (movs, sm))| {
+        .then(metadata().repeated())
+        .then(movements(aliases))
+        .map(|((((date, sd), (desc, sde)), metadata), (movs, sm))| {
             (
                 Op::Transaction(
                     (date.get_date().unwrap(), sd.clone()),
                     (desc.get_description().unwrap(), sde),
                     (movs, sm.clone()),
+                    metadata,
                 ),
                 sd.start()..sm.end(),
             )
         })
 }
 
-pub fn parser() -> impl Parser<Spanned<Token>, Vec<Spanned<Op>>, Error = Simple<Spanned<Token>>> {
-    let ops = open_op()
-        .or(balance_op())
-        .or(transaction_op())
-        .recover_with(skip_then_retry_until([]));
+fn budget_op(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+    keyword("budget")
+        .then(account_ref(aliases))
+        .then(amount())
+        .then(period())
+        .map(|((((_, sk), (acc, sa)), (amount, sm)), (period, sp))| {
+            (
+                Op::Budget(
+                    (acc.get_account().unwrap(), sa),
+                    (amount.get_money().unwrap(), sm),
+                    (period.get_period().unwrap(), sp.clone()),
+                ),
+                sk.start()..sp.end(),
+            )
+        })
+}
+
+fn note_op(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+    date()
+        .then_ignore(keyword("note"))
+        .then(account_ref(aliases))
+        .then(string())
+        .map(|(((date, sd), (acc, sa)), (text, st))| {
+            (
+                Op::Note(
+                    (date.get_date().unwrap(), sd.clone()),
+                    (acc.get_account().unwrap(), sa),
+                    (text.get_description().unwrap(), st.clone()),
+                ),
+                sd.start()..st.end(),
+            )
+        })
+}
 
-    ops.repeated().collect().then_ignore(end())
+fn document_op(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+    date()
+        .then_ignore(keyword("document"))
+        .then(account_ref(aliases))
+        .then(string())
+        .map(|(((date, sd), (acc, sa)), (path, sp))| {
+            (
+                Op::Document(
+                    (date.get_date().unwrap(), sd.clone()),
+                    (acc.get_account().unwrap(), sa),
+                    (path.get_description().unwrap(), sp.clone()),
+                ),
+                sd.start()..sp.end(),
+            )
+        })
 }
 
-pub fn parse_string(filename: &Path, input: &str) -> Result<Vec<Spanned<Op>>> {
-    let lexer = lexer();
-    let parser = parser();
+fn option_op() -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+    keyword("option").then(string()).then(string()).try_map(
+        |(((_, sk), (key, _)), (value, sv)), _: Span| {
+            let span = sk.start()..sv.end();
 
-    let (tokens, errs) = lexer.parse_recovery(input);
-    let (parsed, parse_errs) = match tokens {
-        Some((l, _)) => parser.parse_recovery(l.as_slice()),
-        _ => (Some(vec![]), vec![]),
-    };
+            LedgerOption::parse(
+                &key.get_description().unwrap(),
+                &value.get_description().unwrap(),
+            )
+            .map(|option| (Op::Option((option, span.clone())), span.clone()))
+            .map_err(|msg| Simple::custom(span, msg))
+        },
+    )
+}
 
-    if errs.is_empty() && parse_errs.is_empty() {
-        return Ok(parsed.unwrap());
-    }
+fn alias_op() -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+    let name = filter_map(move |span: Span, token: Spanned<Token>| match token {
+        (Token::Identifier(id), inner)
+            if Keyword::from_str(&id).is_none() && id.parse::<Period>().is_err() =>
+        {
+            Ok((id, inner))
+        }
+        (t, inner) => Err(Simple::expected_input_found(span, vec![], Some((t, inner)))),
+    });
 
-    errs.into_iter()
-        .map(|e| e.map(|c| (c.to_string(), 0..0)))
-        .chain(
-            parse_errs
-                .into_iter()
-                .map(|e| e.map(|(tok, s)| (tok.to_string(), s))),
-        )
-        .for_each(|e| {
-            let span = e.found().map(|x| x.1.clone()).unwrap_or(e.span());
-            let report = Report::build(ReportKind::Error, (), span.start());
-
-            let report = match e.reason() {
-                chumsky::error::SimpleReason::Unclosed { delimiter: (delimiter, _) , .. } => report
-                    .with_message(format!(
-                        "Unclosed delimiter {}",
-                        delimiter.fg(Color::Yellow)
-                    ))
-                    .with_label(
-                        Label::new(span.clone())
-                            .with_message(format!(
-                                "Unclosed delimiter {}",
-                                delimiter.fg(Color::Yellow)
-                            ))
-                            .with_color(Color::Yellow),
-                    )
-                    .with_label(
-                        Label::new(span)
-                            .with_message(format!(
-                                "Must be closed before this {}",
-                                e.found()
-                                    .map(|x| format!("`{}`", x.0).fg(Color::Green).to_string())
-                                    .unwrap()
-                                    .fg(Color::Red)
-                            ))
-                            .with_color(Color::Red),
-                    ),
-                chumsky::error::SimpleReason::Unexpected => report
-                    .with_message(format!(
-                        "{}, expected {}",
-                        if e.found().is_some() {
-                            "Unexpected token in input"
-                        } else {
-                            "Unexpected end of input"
-                        },
-                        if e.expected().len() == 0 {
-                            "end of input".to_string()
-                        } else {
-                            e.expected()
-                                .map(|x| format!("`{}`", x.0).fg(Color::Green).to_string())
-                                .collect::<Vec<_>>()
-                                .join(", ")
-                        }
-                    ))
-                    .with_label(
-                        Label::new(span)
-                            .with_message(format!(
-                                "Unexpected token {}",
-                                e.found().map(|x| format!("`{}`", x.0))
-                                    .unwrap_or("end of file".to_string())
-                                    .fg(Color::Red)
-                            ))
-                            .with_color(Color::Blue),
-                    ),
-                chumsky::error::SimpleReason::Custom(msg) => report.with_message(msg).with_label(
-                    Label::new(span)
-                        .with_message(format!("{}", msg.fg(Color::Red)))
-                        .with_color(Color::Red),
+    keyword("alias")
+        .then(name)
+        .then_ignore(sep('='))
+        .then(account())
+        .map(|(((_, sk), (name, sn)), (acc, sa))| {
+            (
+                Op::Alias((name, sn), (acc.get_account().unwrap(), sa.clone())),
+                sk.start()..sa.end(),
+            )
+        })
+}
+
+fn rename_op() -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+    date()
+        .then_ignore(keyword("rename"))
+        .then(account())
+        .then(account())
+        .map(|(((date, sd), (from, sf)), (to, st))| {
+            (
+                Op::Rename(
+                    (date.get_date().unwrap(), sd.clone()),
+                    (from.get_account().unwrap(), sf),
+                    (to.get_account().unwrap(), st.clone()),
                 ),
-            };
+                sd.start()..st.end(),
+            )
+        })
+}
 
-            report.finish().eprint(Source::from(&input)).unwrap();
+/// A function name inside an assertion, like `sum` or `count`.
+fn function<T: Into<String>>(
+    name: T,
+) -> impl Parser<Spanned<Token>, Spanned<Token>, Error = Simple<Spanned<Token>>> {
+    let name = name.into();
+
+    filter_map(move |span: Span, token: Spanned<Token>| match token {
+        (Token::Identifier(id), inner) if id == name => Ok((Token::Identifier(id), inner)),
+        (t, inner) => Err(Simple::expected_input_found(
+            span,
+            vec![(Token::Identifier(name.clone()), inner.clone())],
+            Some((t, inner)),
+        )),
+    })
+}
+
+fn account_pattern(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<AccountPattern>, Error = Simple<Spanned<Token>>> {
+    account_ref(aliases)
+        .then(sep(':').ignore_then(sep('*')).or_not())
+        .map(|((acc, sa), star)| {
+            let end = star
+                .as_ref()
+                .map(|(_, s)| s.end())
+                .unwrap_or_else(|| sa.end());
+
+            (
+                AccountPattern {
+                    account: acc.get_account().unwrap(),
+                    children_only: star.is_some(),
+                },
+                sa.start()..end,
+            )
+        })
+}
+
+fn assertion_period(
+) -> impl Parser<Spanned<Token>, Spanned<AssertionPeriod>, Error = Simple<Spanned<Token>>> {
+    bounded_number(1000, 3000)
+        .then(sep('-').ignore_then(bounded_number(1, 12)).or_not())
+        .try_map(|((y, sy), month), _: Span| {
+            let end = month
+                .as_ref()
+                .map(|(_, s)| s.end())
+                .unwrap_or_else(|| sy.end());
+            let span = sy.start()..end;
+            let year = y
+                .to_i32()
+                .ok_or(Simple::custom(span.clone(), "Invalid year"))?;
+
+            match month {
+                None => Ok((AssertionPeriod::Year(year), span)),
+                Some((m, _)) => match m.to_u32().filter(|m| (1..=12).contains(m)) {
+                    Some(month) => Ok((AssertionPeriod::Month(year, month), span)),
+                    None => Err(Simple::custom(span, "Invalid month, expected 1 to 12")),
+                },
+            }
+        })
+        .labelled("year or month")
+}
+
+fn query(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Query>, Error = Simple<Spanned<Token>>> {
+    let sum = function("sum")
+        .then_ignore(sep('('))
+        .then(account_pattern(aliases))
+        .then(sep(',').ignore_then(assertion_period()).or_not())
+        .then(sep(')'))
+        .map(|((((_, sf), (pattern, _)), period), (_, sp))| {
+            (
+                Query::Sum(pattern, period.map(|(p, _)| p)),
+                sf.start()..sp.end(),
+            )
         });
 
-    bail!("Parse error...")
+    let balance = function("balance")
+        .then_ignore(sep('('))
+        .then(account_pattern(aliases))
+        .then(sep(')'))
+        .map(|(((_, sf), (pattern, _)), (_, sp))| (Query::Balance(pattern), sf.start()..sp.end()));
+
+    let name = filter_map(move |span: Span, token: Spanned<Token>| match token {
+        (Token::Identifier(id), _) => Ok(id),
+        (Token::String(text), _) => Ok(text),
+        (t, inner) => Err(Simple::expected_input_found(span, vec![], Some((t, inner)))),
+    });
+
+    let count = function("count")
+        .then_ignore(sep('('))
+        .then_ignore(function("tag"))
+        .then_ignore(sep(':'))
+        .then(name)
+        .then(sep(',').ignore_then(name).or_not())
+        .then(sep(')'))
+        .map(|((((_, sf), key), value), (_, sp))| (Query::Count(key, value), sf.start()..sp.end()));
+
+    sum.or(balance).or(count).labelled("sum, balance or count")
+}
+
+fn comparison() -> impl Parser<Spanned<Token>, Spanned<Comparison>, Error = Simple<Spanned<Token>>>
+{
+    let first = filter_map(move |span: Span, token: Spanned<Token>| match token {
+        (Token::Movement(MovementKind::Debit), inner) => Ok((Comparison::Less, inner)),
+        (Token::Movement(MovementKind::Credit), inner) => Ok((Comparison::Greater, inner)),
+        (Token::Separator('='), inner) => Ok((Comparison::Equal, inner)),
+        (Token::Separator('!'), inner) => Ok((Comparison::NotEqual, inner)),
+        (t, inner) => Err(Simple::expected_input_found(span, vec![], Some((t, inner)))),
+    });
+
+    // `<` and `>` lex as movement kinds, so every comparison is one or two tokens.
+    first
+        .then(sep('=').or_not())
+        .try_map(|((first, sf), equals), _: Span| {
+            let end = equals
+                .as_ref()
+                .map(|(_, s)| s.end())
+                .unwrap_or_else(|| sf.end());
+            let span = sf.start()..end;
+
+            match (first, equals.is_some()) {
+                (Comparison::Less, true) => Ok((Comparison::LessOrEqual, span)),
+                (Comparison::Greater, true) => Ok((Comparison::GreaterOrEqual, span)),
+                (Comparison::Less, false) | (Comparison::Greater, false) => Ok((first, span)),
+                (Comparison::Equal, true) | (Comparison::NotEqual, true) => Ok((first, span)),
+                _ => Err(Simple::custom(
+                    span,
+                    "Expected a comparison: <, <=, >, >=, == or !=",
+                )),
+            }
+        })
+        .labelled("comparison")
+}
+
+fn bound() -> impl Parser<Spanned<Token>, Spanned<Bound>, Error = Simple<Spanned<Token>>> {
+    let number = filter_map(move |span: Span, token: Spanned<Token>| match token {
+        (Token::Number(n), inner) => Ok((n, inner)),
+        (t, inner) => Err(Simple::expected_input_found(span, vec![], Some((t, inner)))),
+    });
+
+    let bound = amount()
+        .map(|(amount, sa)| (Bound::Amount(amount.get_money().unwrap()), sa))
+        .or(number.map(|(n, sn)| (Bound::Number(n), sn)));
+
+    sep('-')
+        .or_not()
+        .then(bound)
+        .map(|(minus, (bound, sb))| {
+            let start = minus
+                .as_ref()
+                .map(|(_, s)| s.start())
+                .unwrap_or_else(|| sb.start());
+
+            let bound = match (minus, bound) {
+                (Some(_), Bound::Amount(money)) => Bound::Amount(Money {
+                    amount: -money.amount,
+                    ..money
+                }),
+                (Some(_), Bound::Number(n)) => Bound::Number(-n),
+                (None, bound) => bound,
+            };
+
+            (bound, start..sb.end())
+        })
+        .labelled("amount or number")
+}
+
+fn assert_op(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+    keyword("assert")
+        .then(date().or_not())
+        .then(query(aliases))
+        .then(comparison())
+        .then(bound())
+        .try_map(
+            |(((((_, sk), date), (query, sq)), (comparison, _)), (bound, sb)), _: Span| {
+                let span = sk.start()..sb.end();
+
+                let mismatch = match (&query, &bound) {
+                    (Query::Count(..), Bound::Amount(_)) => {
+                        Some("Counts are compared with a plain number")
+                    }
+                    (Query::Sum(..) | Query::Balance(..), Bound::Number(_)) => {
+                        Some("Sums and balances are compared with an amount, like `0 BRL`")
+                    }
+                    _ => None,
+                };
+
+                if let Some(msg) = mismatch {
+                    return Err(Simple::custom(sb, msg));
+                }
+
+                Ok((
+                    Op::Assert(
+                        date.map(|(date, sd)| (date.get_date().unwrap(), sd)),
+                        (
+                            Assertion {
+                                query,
+                                comparison,
+                                bound,
+                            },
+                            sq.start()..sb.end(),
+                        ),
+                    ),
+                    span,
+                ))
+            },
+        )
+}
+
+/// Finds every `alias` op ahead of parsing, so aliases can be used before they are defined.
+fn collect_aliases(tokens: &[Spanned<Token>]) -> Aliases {
+    let mut aliases = Aliases::new();
+
+    for (i, (token, _)) in tokens.iter().enumerate() {
+        if *token != Token::identifier("alias") {
+            continue;
+        }
+
+        if let Ok((Op::Alias((name, _), (account, _)), _)) = alias_op().parse(&tokens[i..]) {
+            aliases.insert(name, account);
+        }
+    }
+
+    aliases
+}
+
+fn op(
+    aliases: &Aliases,
+) -> impl Parser<Spanned<Token>, Spanned<Op>, Error = Simple<Spanned<Token>>> {
+    open_op()
+        .or(balance_op(aliases))
+        .or(transaction_op(aliases))
+        .or(budget_op(aliases))
+        .or(note_op(aliases))
+        .or(document_op(aliases))
+        .or(option_op())
+        .or(alias_op())
+        .or(rename_op())
+        .or(assert_op(aliases))
+}
+
+/// Splits tokens into one group per op, along with the span of its text. A group starts at every
+/// line that can't continue the op above it, like a dated line, so a broken op never swallows the
+/// ones after it.
+fn split_ops(input: &str, tokens: Vec<Spanned<Token>>) -> Vec<Spanned<Vec<Spanned<Token>>>> {
+    let mut anchors = SyntaxTree::parse(input)
+        .entries
+        .iter()
+        .map(|e| e.span().start)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .peekable();
+    let mut groups: Vec<Spanned<Vec<Spanned<Token>>>> = vec![];
+
+    for token in tokens {
+        let start = token.1.start;
+        let mut starts_op = groups.is_empty();
+
+        while anchors.peek().is_some_and(|a| *a <= start) {
+            anchors.next();
+            starts_op = true;
+        }
+
+        match groups.last_mut() {
+            Some((group, span)) if !starts_op => {
+                span.end = token.1.end;
+                group.push(token);
+            }
+            _ => {
+                let span = start..token.1.end;
+                groups.push((vec![token], span));
+            }
+        }
+    }
+
+    groups
+}
+
+/// Builds the diagnostic for `e`, pointing at `end` when the input ended before it was expected.
+fn syntax_diagnostic(filename: &Path, e: Simple<(String, Span)>, end: Span) -> Diagnostic {
+    let span = e.found().map(|x| x.1.clone()).unwrap_or(end);
+    let found = e
+        .found()
+        .map(|x| format!("`{}`", x.0))
+        .unwrap_or_else(|| "end of input".to_string());
+
+    let diagnostic = match e.reason() {
+        chumsky::error::SimpleReason::Unclosed {
+            span: opening,
+            delimiter: (delimiter, _),
+        } if delimiter == "\"" => {
+            return Diagnostic::error(codes::UNTERMINATED_STRING, "Unterminated string")
+                .with_label(opening.clone(), "This string is never closed")
+                .in_file(filename)
+                .with_span(opening.clone());
+        }
+        chumsky::error::SimpleReason::Unclosed {
+            delimiter: (delimiter, _),
+            ..
+        } => Diagnostic::error(
+            codes::UNCLOSED_DELIMITER,
+            format!("Unclosed delimiter `{}`", delimiter),
+        )
+        .with_label(
+            span.clone(),
+            format!("Must be closed before this {}", found),
+        ),
+        chumsky::error::SimpleReason::Unexpected => Diagnostic::error(
+            codes::UNEXPECTED_TOKEN,
+            format!(
+                "{}{}",
+                if e.found().is_some() {
+                    "Unexpected token in input"
+                } else {
+                    "Unexpected end of input"
+                },
+                if e.expected().len() == 0 {
+                    // Nothing in particular was expected, like when an op stops halfway.
+                    String::new()
+                } else {
+                    // The expected set has no order of its own, so sort it to keep messages stable.
+                    let mut expected = e
+                        .expected()
+                        .map(|x| format!("`{}`", x.0))
+                        .collect::<Vec<_>>();
+                    expected.sort();
+                    expected.dedup();
+                    format!(", expected {}", expected.join(", "))
+                }
+            ),
+        )
+        .with_label(span.clone(), format!("Unexpected token {}", found)),
+        chumsky::error::SimpleReason::Custom(msg) => {
+            Diagnostic::error(codes::INVALID_SYNTAX, msg.clone())
+                .with_label(span.clone(), msg.clone())
+        }
+    };
+
+    diagnostic.in_file(filename).with_span(span)
+}
+
+/// Parses a whole ledger, returning every op that could be parsed along with one diagnostic per
+/// op that couldn't.
+pub fn parse_string_recovery(filename: &Path, input: &str) -> (Vec<Spanned<Op>>, Vec<Diagnostic>) {
+    let (tokens, errs) = lexer().parse_recovery(input);
+
+    let mut diagnostics = errs
+        .into_iter()
+        .map(|e| {
+            let span = e.span();
+            syntax_diagnostic(filename, e.map(|c| (c.to_string(), span.clone())), span)
+        })
+        .collect::<Vec<_>>();
+
+    let tokens = match tokens {
+        Some((tokens, _)) => tokens,
+        None => return (vec![], diagnostics),
+    };
+
+    let aliases = collect_aliases(&tokens);
+    let op = op(&aliases).then_ignore(end());
+    let mut ops = vec![];
+
+    for (group, end) in split_ops(input, tokens) {
+        match op.parse(group.as_slice()) {
+            Ok(parsed) => ops.push(parsed),
+            // Any other error in the same op is almost always caused by the first one.
+            Err(errs) => diagnostics.extend(errs.into_iter().take(1).map(|e| {
+                syntax_diagnostic(
+                    filename,
+                    e.map(|(tok, s)| (tok.to_string(), s)),
+                    end.clone(),
+                )
+            })),
+        }
+    }
+
+    (ops, diagnostics)
+}
+
+/// Parses a whole ledger, returning every syntax error found when it can't be parsed.
+pub fn parse_string(filename: &Path, input: &str) -> Result<Vec<Spanned<Op>>, Diagnostics> {
+    match parse_string_recovery(filename, input) {
+        (ops, diagnostics) if diagnostics.is_empty() => Ok(ops),
+        (_, diagnostics) => Err(Diagnostics(diagnostics)),
+    }
+}
+
+/// Parses a single op from the text of one entry of a [`SyntaxTree`], which starts `offset`
+/// characters into its file. Spans are relative to the whole file.
+pub fn parse_op(
+    filename: &Path,
+    input: &str,
+    offset: usize,
+    aliases: &Aliases,
+) -> Result<Spanned<Op>, Diagnostics> {
+    let span = offset..offset + input.chars().count();
+    let chars = input
+        .chars()
+        .enumerate()
+        .map(|(i, c)| (c, offset + i..offset + i + 1));
+
+    let (tokens, errs) = lexer().parse_recovery(Stream::from_iter(span.end..span.end, chars));
+
+    if let Some(e) = errs.into_iter().next() {
+        let span = e.span();
+        let diagnostic =
+            syntax_diagnostic(filename, e.map(|c| (c.to_string(), span.clone())), span);
+        return Err(Diagnostics(vec![diagnostic]));
+    }
+
+    let tokens = tokens.map(|(tokens, _)| tokens).unwrap_or_default();
+
+    op(aliases)
+        .then_ignore(end())
+        .parse(tokens.as_slice())
+        .map_err(|mut errs| {
+            let e = errs.remove(0).map(|(tok, s)| (tok.to_string(), s));
+            Diagnostics(vec![syntax_diagnostic(filename, e, span)])
+        })
 }
 
 pub fn parse_file<'a, P: AsRef<Path>>(path: P) -> Result<Vec<Spanned<Op>>> {
@@ -399,7 +916,7 @@ pub fn parse_file<'a, P: AsRef<Path>>(path: P) -> Result<Vec<Spanned<Op>>> {
 
     let input = fs::read_to_string(p)?;
 
-    parse_string(path.as_ref(), &input)
+    Ok(parse_string(path.as_ref(), &input)?)
 }
 
 #[cfg(test)]
@@ -483,6 +1000,12 @@ mod tests {
             ("open", Keyword::Open),
             ("balance", Keyword::Balance),
             ("transaction", Keyword::Transaction),
+            ("budget", Keyword::Budget),
+            ("note", Keyword::Note),
+            ("document", Keyword::Document),
+            ("option", Keyword::Option),
+            ("alias", Keyword::Alias),
+            ("rename", Keyword::Rename),
         ] {
             let parser = keyword(*kw);
 
@@ -523,7 +1046,68 @@ mod tests {
                     AccountType::Assets,
                     vec!["cash_account".into(), "omg".into()]
                 ),
-                "BRL".into()
+                vec!["BRL".into()],
+                vec![]
+            ),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_open_with_metadata() -> Result<()> {
+        let parser = open_op();
+
+        let tokens = vec![
+            (Token::number(2020.0), 0..1),
+            (Token::Separator('-'), 0..1),
+            (Token::number(1.0), 0..1),
+            (Token::Separator('-'), 0..1),
+            (Token::number(1.0), 0..1),
+            (Token::identifier("open"), 0..1),
+            (Token::identifier("assets"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::identifier("nubank"), 0..1),
+            (Token::currency("BRL"), 0..1),
+            (Token::currency("USD"), 0..1),
+            (Token::identifier("institution"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::String("Nubank".into()), 0..1),
+            (Token::identifier("number"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::String("1234-5".into()), 0..1),
+        ];
+        assert_eq!(
+            CleanOp::from(parser.parse(tokens.as_slice()).unwrap().0),
+            CleanOp::Open(
+                NaiveDate::from_ymd(2020, 1, 1),
+                Account(AccountType::Assets, vec!["nubank".into()]),
+                vec!["BRL".into(), "USD".into()],
+                vec![
+                    ("institution".into(), "Nubank".into()),
+                    ("number".into(), "1234-5".into())
+                ]
+            ),
+        );
+
+        let tokens = vec![
+            (Token::number(2020.0), 0..1),
+            (Token::Separator('-'), 0..1),
+            (Token::number(1.0), 0..1),
+            (Token::Separator('-'), 0..1),
+            (Token::number(1.0), 0..1),
+            (Token::identifier("open"), 0..1),
+            (Token::identifier("assets"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::identifier("wallet"), 0..1),
+        ];
+        assert_eq!(
+            CleanOp::from(parser.parse(tokens.as_slice()).unwrap().0),
+            CleanOp::Open(
+                NaiveDate::from_ymd(2020, 1, 1),
+                Account(AccountType::Assets, vec!["wallet".into()]),
+                vec![],
+                vec![]
             ),
         );
 
@@ -532,7 +1116,7 @@ mod tests {
 
     #[test]
     fn test_parse_balance() -> Result<()> {
-        let parser = balance_op();
+        let parser = balance_op(&Aliases::new());
 
         let tokens = vec![
             (Token::number(2020.0), 0..1),
@@ -566,7 +1150,7 @@ mod tests {
 
     #[test]
     fn test_parse_transaction() -> Result<()> {
-        let parser = transaction_op();
+        let parser = transaction_op(&Aliases::new());
 
         let tokens = vec![
             (Token::number(2020.0), 0..1),
@@ -618,10 +1202,346 @@ mod tests {
             CleanOp::Transaction(
                 NaiveDate::from_ymd(2020, 1, 1),
                 "this is so cool".into(),
-                movements
+                movements,
+                vec![]
+            ),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_budget() -> Result<()> {
+        let parser = budget_op(&Aliases::new());
+
+        let tokens = vec![
+            (Token::identifier("budget"), 0..1),
+            (Token::identifier("expenses"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::identifier("food"), 0..1),
+            (Token::number(1500.0), 0..1),
+            (Token::currency("BRL"), 0..1),
+            (Token::identifier("monthly"), 0..1),
+        ];
+
+        assert_eq!(
+            CleanOp::from(parser.parse(tokens.as_slice()).unwrap().0),
+            CleanOp::Budget(
+                Account(AccountType::Expenses, vec!["food".into()]),
+                Money::new(int_rational(1500), "BRL"),
+                Period::Monthly
+            ),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_note_and_document() -> Result<()> {
+        let tokens = |keyword: &str, text: &str| {
+            vec![
+                (Token::number(2021.0), 0..1),
+                (Token::Separator('-'), 0..1),
+                (Token::number(5.0), 0..1),
+                (Token::Separator('-'), 0..1),
+                (Token::number(3.0), 0..1),
+                (Token::identifier(keyword), 0..1),
+                (Token::identifier("assets"), 0..1),
+                (Token::Separator(':'), 0..1),
+                (Token::identifier("bank"), 0..1),
+                (Token::String(text.into()), 0..1),
+            ]
+        };
+
+        assert_eq!(
+            CleanOp::from(
+                note_op(&Aliases::new())
+                    .parse(tokens("note", "Called bank about fee").as_slice())
+                    .unwrap()
+                    .0
+            ),
+            CleanOp::Note(
+                NaiveDate::from_ymd(2021, 5, 3),
+                Account(AccountType::Assets, vec!["bank".into()]),
+                "Called bank about fee".into()
+            ),
+        );
+
+        assert_eq!(
+            CleanOp::from(
+                document_op(&Aliases::new())
+                    .parse(tokens("document", "statements/2021-05.pdf").as_slice())
+                    .unwrap()
+                    .0
+            ),
+            CleanOp::Document(
+                NaiveDate::from_ymd(2021, 5, 3),
+                Account(AccountType::Assets, vec!["bank".into()]),
+                "statements/2021-05.pdf".into()
+            ),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_option() -> Result<()> {
+        let parser = option_op();
+
+        let tokens = vec![
+            (Token::identifier("option"), 0..1),
+            (Token::String("operating_currency".into()), 0..1),
+            (Token::String("BRL".into()), 0..1),
+        ];
+
+        assert_eq!(
+            CleanOp::from(parser.parse(tokens.as_slice()).unwrap().0),
+            CleanOp::Option(LedgerOption::OperatingCurrency("BRL".into())),
+        );
+
+        let tokens = vec![
+            (Token::identifier("option"), 0..1),
+            (Token::String("operating_currency".into()), 0..1),
+            (Token::String("brl".into()), 0..1),
+        ];
+
+        assert!(parser.parse(tokens.as_slice()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_alias() -> Result<()> {
+        let tokens = vec![
+            (Token::identifier("alias"), 0..1),
+            (Token::identifier("nubank"), 0..1),
+            (Token::Separator('='), 0..1),
+            (Token::identifier("assets"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::identifier("bank"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::identifier("nubank"), 0..1),
+            (Token::Movement(MovementKind::Credit), 0..1),
+            (Token::number(100.0), 0..1),
+            (Token::currency("BRL"), 0..1),
+            (Token::identifier("nubank"), 0..1),
+        ];
+
+        let nubank = Account(AccountType::Assets, vec!["bank".into(), "nubank".into()]);
+        let aliases = collect_aliases(&tokens);
+
+        assert_eq!(aliases.get("nubank"), Some(&nubank));
+
+        assert_eq!(
+            CleanOp::from(alias_op().parse(&tokens[..8]).unwrap().0),
+            CleanOp::Alias("nubank".into(), nubank.clone()),
+        );
+
+        assert_eq!(
+            movement(&aliases).parse(&tokens[8..]).unwrap().0,
+            Movement(
+                MovementKind::Credit,
+                Money::new(int_rational(100), "BRL"),
+                nubank
             ),
         );
 
+        assert!(movement(&Aliases::new()).parse(&tokens[8..]).is_err());
+
         Ok(())
     }
+
+    #[test]
+    fn test_parse_account_rejects_equals() {
+        let input = "2020-01-01 open assets:bank=nubank BRL\n";
+        let Diagnostics(diagnostics) = parse_string(Path::new("books.hta"), input).unwrap_err();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].span.clone().map(|s| &input[s]), Some("="));
+    }
+
+    #[test]
+    fn test_parse_account_only_takes_colons() {
+        for separator in ['-', '(', ',', '*', '!'] {
+            let tokens = vec![
+                (Token::identifier("assets"), 0..1),
+                (Token::Separator(':'), 1..2),
+                (Token::identifier("bank"), 2..3),
+                (Token::Separator(separator), 3..4),
+                (Token::identifier("nubank"), 4..5),
+            ];
+
+            assert!(account()
+                .then_ignore(end())
+                .parse(tokens.as_slice())
+                .is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_rename() -> Result<()> {
+        let tokens = vec![
+            (Token::number(2022.0), 0..1),
+            (Token::Separator('-'), 0..1),
+            (Token::number(1.0), 0..1),
+            (Token::Separator('-'), 0..1),
+            (Token::number(1.0), 0..1),
+            (Token::identifier("rename"), 0..1),
+            (Token::identifier("assets"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::identifier("nubank"), 0..1),
+            (Token::identifier("assets"), 0..1),
+            (Token::Separator(':'), 0..1),
+            (Token::identifier("inter"), 0..1),
+        ];
+
+        assert_eq!(
+            CleanOp::from(rename_op().parse(tokens.as_slice()).unwrap().0),
+            CleanOp::Rename(
+                NaiveDate::from_ymd(2022, 1, 1),
+                Account(AccountType::Assets, vec!["nubank".into()]),
+                Account(AccountType::Assets, vec!["inter".into()]),
+            ),
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_assert() -> Result<()> {
+        let input = r#"alias food = expenses:food
+assert 2021-12-31 sum(food, 2021) < 20000 BRL
+assert count(tag:reimbursable, open) == 0
+assert balance(assets:bank:*) >= -10.5 BRL
+"#;
+        let ops = parse_string(Path::new("books.hta"), input)?
+            .into_iter()
+            .map(CleanOp::from)
+            .collect::<Vec<_>>();
+        let food = Account(AccountType::Expenses, vec!["food".into()]);
+        let bank = Account(AccountType::Assets, vec!["bank".into()]);
+
+        assert_eq!(
+            ops[1..],
+            [
+                CleanOp::Assert(
+                    Some(NaiveDate::from_ymd(2021, 12, 31)),
+                    Assertion {
+                        query: Query::Sum(
+                            AccountPattern {
+                                account: food,
+                                children_only: false
+                            },
+                            Some(AssertionPeriod::Year(2021))
+                        ),
+                        comparison: Comparison::Less,
+                        bound: Bound::Amount(Money::new(int_rational(20000), "BRL")),
+                    }
+                ),
+                CleanOp::Assert(
+                    None,
+                    Assertion {
+                        query: Query::Count("reimbursable".into(), Some("open".into())),
+                        comparison: Comparison::Equal,
+                        bound: Bound::Number(int_rational(0)),
+                    }
+                ),
+                CleanOp::Assert(
+                    None,
+                    Assertion {
+                        query: Query::Balance(AccountPattern {
+                            account: bank,
+                            children_only: true
+                        }),
+                        comparison: Comparison::GreaterOrEqual,
+                        bound: Bound::Amount(Money::new(
+                            BigRational::new((-21).into(), 2.into()),
+                            "BRL"
+                        )),
+                    }
+                ),
+            ]
+        );
+
+        let input = "assert count(tag:reimbursable) == 0 BRL
+assert balance(assets:bank) = 0 BRL
+";
+        let (_, diagnostics) = parse_string_recovery(Path::new("books.hta"), input);
+
+        assert_eq!(
+            diagnostics
+                .iter()
+                .map(|d| d.message.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "Counts are compared with a plain number",
+                "Expected a comparison: <, <=, >, >=, == or !="
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_errors_are_diagnostics() {
+        let input = "2020-01-01 open assets:cash BRL\n2020-01-02 balanse assets:cash 0 BRL\n";
+        let Diagnostics(diagnostics) = parse_string(Path::new("books.hta"), input).unwrap_err();
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, codes::UNEXPECTED_TOKEN);
+        assert_eq!(diagnostics[0].file, Path::new("books.hta"));
+        assert_eq!(
+            diagnostics[0]
+                .span
+                .clone()
+                .map(|s| input[s].trim().to_string()),
+            Some("balanse".to_string())
+        );
+        assert_eq!(
+            diagnostics[0].message,
+            "Unexpected token in input, expected `balance`, `document`, `note`, `open`, `rename`, `transaction`"
+        );
+    }
+
+    #[test]
+    fn test_parse_recovers_at_next_op() {
+        let input = r#"2020-01-01 open assets:cash BRL
+2020-01-02 transaction "Broken"
+  > 10 BRL
+  < 10 BRL assets:cash
+// The ops below are still parsed.
+2020-01-03 balance assets:cash 0 BRL
+option "display_precision" "4"
+2020-01-04 balanse assets:cash 0 BRL
+2020-01-05 note assets:cash "Fine"
+"#;
+        let (ops, diagnostics) = parse_string_recovery(Path::new("books.hta"), input);
+
+        assert_eq!(ops.len(), 4);
+        assert!(matches!(ops[1].0, Op::Balance(..)));
+        assert!(matches!(ops[3].0, Op::Note(..)));
+
+        let found = diagnostics
+            .iter()
+            .map(|d| d.span.clone().map(|s| input[s].trim().to_string()))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            vec![Some("<".to_string()), Some("balanse".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_string_points_at_opening_quote() {
+        let input = "2020-01-01 open assets:cash BRL\n\
+            2020-01-02 transaction \"Groceries\n\
+            \x20 > 10 BRL assets:cash\n\
+            \x20 < 10 BRL expenses:food\n";
+        let (_, diagnostics) = parse_string_recovery(Path::new("books.hta"), input);
+
+        assert_eq!(diagnostics[0].code, codes::UNTERMINATED_STRING);
+        assert_eq!(diagnostics[0].span, Some(55..56));
+        assert_eq!(&input[55..56], "\"");
+    }
 }
@@ -0,0 +1,353 @@
+//! Prints ops back as ledger source.
+//!
+//! The output always parses back into the same ops, which lets importers and other tools
+//! generate ledger files without going through strings themselves. Aliases are never used
+//! when printing accounts, and text with line breaks is printed in triple quotes.
+
+use std::fmt::{self, Display};
+
+use super::CleanOp;
+
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Text printed as a string literal, escaping whatever would end it early.
+pub(crate) struct Quoted<'a>(pub(crate) &'a str);
+
+impl Display for Quoted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let quotes = if self.0.contains('\n') {
+            "\"\"\""
+        } else {
+            "\""
+        };
+
+        write!(f, "{}", quotes)?;
+
+        for c in self.0.chars() {
+            match c {
+                '"' => write!(f, "\\\"")?,
+                '\\' => write!(f, "\\\\")?,
+                '\n' => writeln!(f)?,
+                c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+
+        write!(f, "{}", quotes)
+    }
+}
+
+impl Display for CleanOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CleanOp::Open(date, account, currencies, metadata) => {
+                write!(f, "{} open {}", date.format(DATE_FORMAT), account)?;
+
+                for currency in currencies {
+                    write!(f, " {}", currency.0)?;
+                }
+
+                for (key, value) in metadata {
+                    write!(f, "\n  {}: {}", key, Quoted(value))?;
+                }
+
+                Ok(())
+            }
+            CleanOp::Balance(date, account, money) => {
+                write!(
+                    f,
+                    "{} balance {} {}",
+                    date.format(DATE_FORMAT),
+                    account,
+                    money
+                )
+            }
+            CleanOp::Transaction(date, description, movements, metadata) => {
+                write!(
+                    f,
+                    "{} transaction {}",
+                    date.format(DATE_FORMAT),
+                    Quoted(description)
+                )?;
+
+                for (key, value) in metadata {
+                    write!(f, "\n  {}: {}", key, Quoted(value))?;
+                }
+
+                for movement in movements {
+                    write!(f, "\n  {}", movement)?;
+                }
+
+                Ok(())
+            }
+            CleanOp::Budget(account, money, period) => {
+                write!(f, "budget {} {} {}", account, money, period)
+            }
+            CleanOp::Note(date, account, text) => {
+                write!(
+                    f,
+                    "{} note {} {}",
+                    date.format(DATE_FORMAT),
+                    account,
+                    Quoted(text)
+                )
+            }
+            CleanOp::Document(date, account, path) => {
+                write!(
+                    f,
+                    "{} document {} {}",
+                    date.format(DATE_FORMAT),
+                    account,
+                    Quoted(path)
+                )
+            }
+            CleanOp::Option(option) => {
+                write!(
+                    f,
+                    "option {} {}",
+                    Quoted(&option.key()),
+                    Quoted(&option.value())
+                )
+            }
+            CleanOp::Alias(name, account) => write!(f, "alias {} = {}", name, account),
+            CleanOp::Rename(date, from, to) => {
+                write!(f, "{} rename {} {}", date.format(DATE_FORMAT), from, to)
+            }
+            CleanOp::Assert(Some(date), assertion) => {
+                write!(f, "assert {} {}", date.format(DATE_FORMAT), assertion)
+            }
+            CleanOp::Assert(None, assertion) => write!(f, "assert {}", assertion),
+        }
+    }
+}
+
+/// Prints a whole program, one op after the other.
+pub fn print(ops: &[CleanOp]) -> String {
+    ops.iter().map(|op| format!("{}\n", op)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use chrono::NaiveDate;
+    use num::{BigInt, BigRational};
+    use proptest::{collection::vec, prelude::*};
+
+    use super::*;
+    use crate::{
+        account::{Account, AccountType},
+        assertion::{AccountPattern, Assertion, AssertionPeriod, Bound, Comparison, Query},
+        budget::Period,
+        diagnostic::Severity,
+        money::{Currency, Money, Movement, MovementKind},
+        options::{FiscalYearStart, LedgerOption},
+        syntax::parse_string,
+    };
+
+    fn parse(source: &str) -> Vec<CleanOp> {
+        parse_string(Path::new("printed.hta"), source)
+            .unwrap()
+            .into_iter()
+            .map(CleanOp::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_print() {
+        let source = r#"2020-01-01 open assets:bank BRL USD
+  institution: "Some Bank"
+2020-01-02 transaction "Salary"
+  payslip: "2020-01"
+  > 1000.5 BRL income:salary
+  < 1000.5 BRL assets:bank
+budget expenses:food 500 BRL monthly
+option "default_tolerance" "0.005"
+alias bank = assets:bank
+2020-02-01 rename assets:bank assets:old_bank
+assert 2020-12-31 sum(expenses:food, 2020-03) <= 500 BRL
+assert balance(assets:bank:*) >= -20 BRL
+assert count(tag:payslip, "2020-01") == 1
+"#;
+
+        assert_eq!(print(&parse(source)), source);
+    }
+
+    #[test]
+    fn test_print_escapes() {
+        let date = NaiveDate::from_ymd(2020, 1, 1);
+        let account = Account(AccountType::Assets, vec!["bank".to_string()]);
+        let ops = vec![
+            CleanOp::Document(date, account.clone(), r#"C:\docs\"a".pdf"#.to_string()),
+            CleanOp::Note(date, account, "Moved to\n\"new\" bank\t".to_string()),
+        ];
+
+        assert_eq!(
+            print(&ops),
+            r#"2020-01-01 document assets:bank "C:\\docs\\\"a\".pdf"
+2020-01-01 note assets:bank """Moved to
+\"new\" bank\u{9}"""
+"#
+        );
+        assert_eq!(parse(&print(&ops)), ops);
+    }
+
+    fn date() -> impl Strategy<Value = NaiveDate> {
+        (1000..3000, 1..=12u32, 1..=28u32).prop_map(|(y, m, d)| NaiveDate::from_ymd(y, m, d))
+    }
+
+    fn name() -> impl Strategy<Value = String> {
+        "[a-z][a-z_]{0,8}"
+    }
+
+    fn text() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9 .,/_\"\\\\\n\téã€-]{0,20}"
+    }
+
+    fn currency() -> impl Strategy<Value = Currency> {
+        "[A-Z]{3,5}".prop_map(Currency::from)
+    }
+
+    fn decimal() -> impl Strategy<Value = BigRational> {
+        (0..10_000_000u64, 0..6u32)
+            .prop_map(|(n, places)| BigRational::new(BigInt::from(n), BigInt::from(10).pow(places)))
+    }
+
+    fn money() -> impl Strategy<Value = Money> {
+        let amount = prop_oneof![
+            decimal().prop_map(|d| num::ToPrimitive::to_f64(&d).unwrap()),
+            0.0..1e15f64,
+        ];
+
+        (amount, currency()).prop_map(|(a, c)| Money::new(BigRational::from_float(a).unwrap(), c))
+    }
+
+    fn account() -> impl Strategy<Value = Account> {
+        let kind = prop_oneof![
+            Just(AccountType::Assets),
+            Just(AccountType::Liabilities),
+            Just(AccountType::Income),
+            Just(AccountType::Equity),
+            Just(AccountType::Expenses),
+        ];
+
+        (kind, vec(name(), 1..4)).prop_map(|(kind, parts)| Account(kind, parts))
+    }
+
+    fn movement() -> impl Strategy<Value = Movement> {
+        let kind = prop_oneof![Just(MovementKind::Credit), Just(MovementKind::Debit)];
+
+        (kind, money(), account()).prop_map(|(k, m, a)| Movement(k, m, a))
+    }
+
+    fn period() -> impl Strategy<Value = Period> {
+        prop_oneof![
+            Just(Period::Weekly),
+            Just(Period::Monthly),
+            Just(Period::Quarterly),
+            Just(Period::Yearly),
+        ]
+    }
+
+    fn option() -> impl Strategy<Value = LedgerOption> {
+        prop_oneof![
+            currency().prop_map(LedgerOption::OperatingCurrency),
+            (1..=12u32, 1..=28u32).prop_map(|(month, day)| {
+                LedgerOption::FiscalYearStart(FiscalYearStart { month, day })
+            }),
+            decimal().prop_map(LedgerOption::DefaultTolerance),
+            any::<u32>().prop_map(LedgerOption::DisplayPrecision),
+            any::<u32>().prop_map(LedgerOption::DuplicateWindow),
+            any::<bool>().prop_map(LedgerOption::StrictDateOrder),
+            (
+                name(),
+                prop_oneof![
+                    Just(None),
+                    Just(Some(Severity::Error)),
+                    Just(Some(Severity::Warning)),
+                    Just(Some(Severity::Note)),
+                ]
+            )
+                .prop_map(|(n, s)| LedgerOption::Rule(n, s)),
+        ]
+    }
+
+    fn alias_name() -> impl Strategy<Value = String> {
+        name().prop_filter("alias names can't be keywords or periods", |n| {
+            crate::syntax::Keyword::from_str(n).is_none() && n.parse::<Period>().is_err()
+        })
+    }
+
+    fn pattern() -> impl Strategy<Value = AccountPattern> {
+        (account(), any::<bool>()).prop_map(|(account, children_only)| AccountPattern {
+            account,
+            children_only,
+        })
+    }
+
+    fn assertion() -> impl Strategy<Value = Assertion> {
+        let period = prop_oneof![
+            (1000..3000).prop_map(AssertionPeriod::Year),
+            (1000..3000, 1..=12u32).prop_map(|(y, m)| AssertionPeriod::Month(y, m)),
+        ];
+        let comparison = prop_oneof![
+            Just(Comparison::Less),
+            Just(Comparison::LessOrEqual),
+            Just(Comparison::Greater),
+            Just(Comparison::GreaterOrEqual),
+            Just(Comparison::Equal),
+            Just(Comparison::NotEqual),
+        ];
+        let query = prop_oneof![
+            (pattern(), proptest::option::of(period), money())
+                .prop_map(|(a, p, m)| (Query::Sum(a, p), Bound::Amount(m))),
+            (pattern(), money()).prop_map(|(a, m)| (Query::Balance(a), Bound::Amount(m))),
+            (name(), proptest::option::of(text()), 0..1000u32).prop_map(|(k, v, n)| {
+                (
+                    Query::Count(k, v),
+                    Bound::Number(BigRational::from_integer(n.into())),
+                )
+            }),
+        ];
+
+        (query, comparison).prop_map(|((query, bound), comparison)| Assertion {
+            query,
+            comparison,
+            bound,
+        })
+    }
+
+    fn op() -> impl Strategy<Value = CleanOp> {
+        prop_oneof![
+            (
+                date(),
+                account(),
+                vec(currency(), 0..3),
+                vec((name(), text()), 0..3)
+            )
+                .prop_map(|(d, a, c, m)| CleanOp::Open(d, a, c, m)),
+            (date(), account(), money()).prop_map(|(d, a, m)| CleanOp::Balance(d, a, m)),
+            (
+                date(),
+                text(),
+                vec(movement(), 1..4),
+                vec((name(), text()), 0..3)
+            )
+                .prop_map(|(d, t, m, md)| CleanOp::Transaction(d, t, m, md)),
+            (account(), money(), period()).prop_map(|(a, m, p)| CleanOp::Budget(a, m, p)),
+            (date(), account(), text()).prop_map(|(d, a, t)| CleanOp::Note(d, a, t)),
+            (date(), account(), text()).prop_map(|(d, a, t)| CleanOp::Document(d, a, t)),
+            option().prop_map(CleanOp::Option),
+            (alias_name(), account()).prop_map(|(n, a)| CleanOp::Alias(n, a)),
+            (date(), account(), account()).prop_map(|(d, f, t)| CleanOp::Rename(d, f, t)),
+            (proptest::option::of(date()), assertion()).prop_map(|(d, a)| CleanOp::Assert(d, a)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_print_round_trip(ops in vec(op(), 0..8)) {
+            prop_assert_eq!(parse(&print(&ops)), ops);
+        }
+    }
+}
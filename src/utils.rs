@@ -1,3 +1,4 @@
+use num::{BigInt, BigRational, One, Signed, ToPrimitive, Zero};
 use polars::prelude::*;
 
 pub fn repeater<T: Clone>(value: T, amount: usize) -> Series
@@ -17,3 +18,53 @@ pub fn round_to_fixed<T: Into<f64>>(series: &Series, precision: T) -> Result<Ser
         .cast(&DataType::Float64)?
         .divide(&repeat100)?)
 }
+
+/// Parses a non-negative decimal number like `12.034` into an exact rational.
+pub fn parse_decimal(value: &str) -> Option<BigRational> {
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+
+    if integer.is_empty() || !(integer.chars().chain(fraction.chars())).all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let numerator = format!("{}{}", integer, fraction).parse::<BigInt>().ok()?;
+    let denominator = BigInt::from(10).pow(fraction.len() as u32);
+
+    Some(BigRational::new(numerator, denominator))
+}
+
+/// Writes `value` as a decimal number that reads back as the same value, or rounded when it has
+/// no exact decimal.
+pub fn format_decimal(value: &BigRational) -> String {
+    let (two, five) = (BigInt::from(2), BigInt::from(5));
+    let mut denom = value.denom().clone();
+    let mut places = (0, 0);
+
+    while (&denom % &two).is_zero() {
+        denom /= &two;
+        places.0 += 1;
+    }
+
+    while (&denom % &five).is_zero() {
+        denom /= &five;
+        places.1 += 1;
+    }
+
+    if !denom.is_one() {
+        return value.to_f64().unwrap_or_default().to_string();
+    }
+
+    let places = places.0.max(places.1);
+
+    if places == 0 {
+        return value.to_integer().to_string();
+    }
+
+    let scaled = (value * BigRational::from_integer(BigInt::from(10).pow(places))).to_integer();
+    let digits = format!("{:0>width$}", scaled.abs(), width = places as usize + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - places as usize);
+    let sign = if scaled.is_negative() { "-" } else { "" };
+
+    format!("{}{}.{}", sign, integer, fraction)
+}
@@ -1,20 +1,27 @@
-use std::path::Path;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Display,
+    path::Path,
+};
 
-use anyhow::{bail, Result};
-use ariadne::{Color, Fmt, Label, Report, ReportKind, Source};
-use polars::prelude::*;
-use thiserror::Error;
+use anyhow::{anyhow, bail, Result};
+use chrono::{Local, NaiveDate};
+use num::{BigRational, Signed, ToPrimitive, Zero};
+use regex::Regex;
 
-use crate::{ledger::Ledger, syntax::Span};
-
-#[derive(Debug, Error)]
-pub enum ValidationError {
-    #[error("Validation error")]
-    WithTrace(Vec<ValidationTrace>),
-
-    #[error("Something happened with the Dataframe...")]
-    DataframeError(#[from] polars::error::PolarsError),
-}
+use crate::{
+    account::{is_same_or_child, Account},
+    activity::unused,
+    chart::{find_opening, AccountOpening},
+    codes,
+    diagnostic::{Diagnostic, Fix, Severity, TextEdit},
+    ledger::Ledger,
+    money::{Currency, Money, Movement, MovementKind},
+    options::LedgerOptions,
+    register::AccountDocument,
+    syntax::{Span, Spanned},
+    LedgerContext,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ValidationTrace {
@@ -23,167 +30,2246 @@ pub struct ValidationTrace {
     span: Option<Span>,
     found: Option<String>,
     expected: Option<String>,
+    /// Other parts of the source involved in the problem, with a message for each.
+    related: Vec<(Span, String)>,
+    fix: Option<Fix>,
+}
+
+impl ValidationTrace {
+    pub fn into_diagnostic(self, code: &'static str, filename: &Path) -> Diagnostic {
+        let message_parts = vec![
+            Some(self.message),
+            self.expected.map(|x| format!("expected `{}`", x)),
+            self.found.map(|f| format!("found {}", f)),
+        ];
+
+        let message = message_parts
+            .into_iter()
+            .flatten()
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let mut diagnostic = self.related.into_iter().fold(
+            Diagnostic::error(code, message).in_file(filename),
+            |diagnostic, (span, message)| diagnostic.with_label(span, message),
+        );
+
+        if let Some(fix) = self.fix {
+            diagnostic = diagnostic.with_fix(fix);
+        }
+
+        match self.span {
+            Some(span) => diagnostic
+                .with_label(span.clone(), self.details)
+                .with_span(span),
+            None => diagnostic.with_note(self.details),
+        }
+    }
+
+    pub(crate) fn unbalanced_ledger(
+        currency: &str,
+        date: NaiveDate,
+        difference: &BigRational,
+        span: Option<Span>,
+        options: &LedgerOptions,
+    ) -> Self {
+        let precision = options.display_precision as usize;
+
+        Self {
+            message: format!("Books do not balance in `{}` from {}", currency, date),
+            details:
+                "The books stop balancing with this transaction. In a double-entry accounting \
+                system, credits and debits of each currency should balance on every day."
+                    .into(),
+            span,
+            found: Some(format!(
+                "{:.2$} {}",
+                difference.to_f64().unwrap_or(f64::NAN),
+                currency,
+                precision
+            )),
+            expected: Some(format!("{:.2$} {}", 0.0, currency, precision)),
+            related: vec![],
+            fix: None,
+        }
+    }
+
+    pub(crate) fn unbalanced_transaction<T: Display>(
+        sum: T,
+        span: Option<Span>,
+        movements: &[Spanned<Movement>],
+    ) -> Self {
+        Self {
+            message: "Transaction does not balance".into(),
+            details: "Inside a transaction, all debits and credits must balance in the end."
+                .to_string(),
+            found: Some(format!("{:.1$}", sum, 2)),
+            expected: Some("0.0".to_string()),
+            span,
+            related: vec![],
+            fix: residual_fix(movements),
+        }
+    }
+
+    pub(crate) fn currency_not_allowed(
+        account: &str,
+        currency: Currency,
+        span: Option<Span>,
+        opening: &AccountOpening,
+    ) -> Self {
+        Self {
+            message: format!("Currency not allowed for account `{}`", account),
+            details: "This account was opened with a restricted list of currencies.".into(),
+            span,
+            found: Some(currency.0),
+            expected: Some(
+                opening
+                    .currencies
+                    .iter()
+                    .map(|c| c.0.clone())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            ),
+            related: vec![],
+            fix: None,
+        }
+    }
+
+    pub(crate) fn duplicate_transaction(earlier: &Candidate, later: &Candidate) -> Self {
+        Self {
+            message: format!(
+                "Transaction looks like a duplicate of the one on {}",
+                earlier.date
+            ),
+            details: "Same amounts and accounts as an earlier transaction, with a similar \
+                description. Add a `repeated` key if this is intentional."
+                .into(),
+            span: Some(later.span.clone()),
+            found: None,
+            expected: None,
+            related: vec![(earlier.span.clone(), "First written here".to_string())],
+            fix: None,
+        }
+    }
+
+    pub(crate) fn abnormal_balance(
+        account: &Account,
+        currency: &str,
+        date: NaiveDate,
+        balance: &BigRational,
+        span: Option<Span>,
+        options: &LedgerOptions,
+    ) -> Self {
+        let (normal, abnormal) = match account.signed_factor(MovementKind::Debit) {
+            1 => ("debit", "credit"),
+            _ => ("credit", "debit"),
+        };
+
+        Self {
+            message: format!(
+                "Balance of `{}` goes to the {} side on {}",
+                account, abnormal, date
+            ),
+            details: format!(
+                "This account normally has a {} balance, so the sides of this movement may be \
+                swapped. Add an `abnormal_balance` key to its `open` if this is expected.",
+                normal
+            ),
+            span,
+            found: Some(format!(
+                "{:.2$} {}",
+                balance.to_f64().unwrap_or(f64::NAN),
+                currency,
+                options.display_precision as usize
+            )),
+            expected: None,
+            related: vec![],
+            fix: None,
+        }
+    }
+
+    pub(crate) fn unused_account(opening: &AccountOpening) -> Self {
+        Self {
+            message: format!("Account `{}` is opened but never used", opening.account),
+            details: "No movement uses this account or the ones below it.".into(),
+            span: Some(opening.span.clone()),
+            found: None,
+            expected: None,
+            related: vec![],
+            fix: None,
+        }
+    }
+
+    pub(crate) fn unopened_account(account: &str, first: &AccountUse) -> Self {
+        let open = format!("{} open {}", first.date, first.name);
+
+        Self {
+            message: format!("Account `{}` is used but never opened", account),
+            details: "No `open` directive opens this account or one above it.".into(),
+            span: Some(first.span.clone()),
+            found: None,
+            expected: None,
+            related: vec![],
+            fix: Some(Fix::new(
+                format!("Add `{}`", open),
+                vec![TextEdit::Replace(
+                    first.op.start..first.op.start,
+                    format!("{}\n", open),
+                )],
+            )),
+        }
+    }
+
+    fn account_naming(
+        message: String,
+        option: &str,
+        expected: Option<String>,
+        span: &Span,
+    ) -> Self {
+        Self {
+            message,
+            details: format!(
+                "The ledger restricts account names with the `{}` option.",
+                option
+            ),
+            span: Some(span.clone()),
+            found: None,
+            expected,
+            related: vec![],
+            fix: None,
+        }
+    }
+
+    pub(crate) fn missing_document(document: &AccountDocument) -> Self {
+        Self {
+            message: format!("Document for `{}` does not exist", document.account),
+            details: "Document paths are relative to the ledger file.".into(),
+            span: Some(document.span.clone()),
+            found: Some(document.path.clone()),
+            expected: None,
+            related: vec![],
+            fix: None,
+        }
+    }
+}
+
+/// Adds the movement that balances a transaction whose movements all use the same currency. It
+/// goes to the account of the last movement, usually the one written to balance the others.
+fn residual_fix(movements: &[Spanned<Movement>]) -> Option<Fix> {
+    let (Movement(_, last, account), span) = movements.last()?;
+
+    if movements.iter().any(|(m, _)| m.1.currency != last.currency) {
+        return None;
+    }
+
+    let sum = movements
+        .iter()
+        .fold(BigRational::zero(), |sum, (m, _)| match m.0 {
+            MovementKind::Credit => sum + &m.1.amount,
+            MovementKind::Debit => sum - &m.1.amount,
+        });
+
+    let kind = if sum.is_positive() {
+        MovementKind::Debit
+    } else {
+        MovementKind::Credit
+    };
+    let residual = Movement(
+        kind,
+        Money::new(sum.abs(), last.currency.clone()),
+        account.clone(),
+    );
+
+    Some(Fix::new(
+        format!("Add `{}` to balance the transaction", residual),
+        vec![TextEdit::AddLines(
+            span.clone(),
+            format!("  {}\n", residual),
+        )],
+    ))
+}
+
+/// A check run over the whole ledger once it was computed.
+///
+/// Besides the built-in validators, library users can register their own with
+/// [`ValidationRunner::register`]. Each one has a name that ledgers use to turn it off or change
+/// its severity, like `option "rule.missing_document" "warning"`.
+pub trait Validator {
+    /// The name used to configure the validator, in snake case.
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    /// The code of the diagnostics it reports.
+    fn code(&self) -> &'static str;
+
+    /// The severity of what it reports, unless the ledger sets another one with a `rule.*`
+    /// option.
+    fn severity(&self, _options: &LedgerOptions) -> Severity {
+        Severity::Error
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>>;
+}
+
+/// Reports the `rule.*` options of the ledger that don't configure any validator in `validators`.
+pub(crate) fn unknown_rules<'a>(
+    filename: &Path,
+    validators: impl Iterator<Item = &'a dyn Validator>,
+    context: &LedgerContext,
+) -> Vec<Diagnostic> {
+    let mut known = validators.map(|v| v.name()).collect::<Vec<_>>();
+    known.sort_unstable();
+
+    context
+        .rule_spans
+        .iter()
+        .filter(|(name, _)| known.binary_search(&name.as_str()).is_err())
+        .map(|(name, span)| {
+            Diagnostic::warning(codes::UNKNOWN_RULE, format!("Unknown rule `{}`", name))
+                .with_label(span.clone(), "No validator uses this rule")
+                .with_note(format!("Known rules are {}", known.join(", ")))
+                .in_file(filename)
+                .with_span(span.clone())
+        })
+        .collect()
+}
+
+/// Applies the ledger's configuration for `validator` to the diagnostics it reported, dropping
+/// them if it was turned off.
+pub(crate) fn configure(
+    validator: &dyn Validator,
+    diagnostics: Vec<Diagnostic>,
+    options: &LedgerOptions,
+) -> Vec<Diagnostic> {
+    let severity = match options.rules.get(validator.name()) {
+        Some(None) => return vec![],
+        Some(Some(severity)) => *severity,
+        None => validator.severity(options),
+    };
+
+    diagnostics
+        .into_iter()
+        .map(|d| Diagnostic { severity, ..d })
+        .collect()
+}
+
+pub struct ValidationRunner {
+    validators: Vec<Box<dyn Validator>>,
 }
 
-pub struct ValidationRunner;
+impl Default for ValidationRunner {
+    /// A runner with every built-in validator.
+    fn default() -> Self {
+        Self {
+            validators: vec![
+                Box::new(LedgerBalance),
+                Box::new(TransactionBalance),
+                Box::new(AllowedCurrencies),
+                Box::new(DocumentsExist),
+                Box::new(BalanceAssertions),
+                Box::new(Assertions),
+                Box::new(DuplicateTransactions),
+                Box::new(DateOrder),
+                Box::new(FutureDates::default()),
+                Box::new(AbnormalBalances),
+                Box::new(UnusedAccounts),
+                Box::new(UnopenedAccounts),
+                Box::new(AccountNaming),
+                Box::new(SingleMovements),
+                Box::new(ZeroAmounts),
+                Box::new(OneSidedTransactions),
+            ],
+        }
+    }
+}
 
 impl ValidationRunner {
-    pub fn run_all(filename: &Path, input: &str, ledger: &Ledger) -> Result<()> {
-        for (name, validator) in ALL_VALIDATORS {
-            print!("Running validator: {}...", name);
+    /// A runner without any validator.
+    pub fn empty() -> Self {
+        Self { validators: vec![] }
+    }
 
-            match validator(&ledger.clone()) {
-                Ok(_) => {
-                    println!(" OK");
-                }
-                Err(ValidationError::WithTrace(traces)) => {
-                    println!(" ERROR");
-
-                    traces.into_iter().for_each(|t| {
-                        let span = t.span.clone().unwrap_or(0..1);
-                        let report = Report::build(ReportKind::Error, (), span.start);
-
-                        let message_parts = vec![
-                            Some(t.message),
-                            t.expected
-                                .map(|x| format!("`{}`", x).fg(Color::Red).to_string()),
-                            t.found.map(|f| format!("found {}", f.fg(Color::Blue))),
-                        ];
-
-                        let message = message_parts
-                            .into_iter()
-                            .filter_map(|x| x)
-                            .collect::<Vec<String>>();
-
-                        let mut report = report.with_message(message.join(", "));
-
-                        if t.span.is_some() {
-                            report = report.with_label(
-                                Label::new(span)
-                                    .with_message(t.details)
-                                    .with_color(Color::Blue),
-                            );
-                        }
-
-                        report.finish().eprint(Source::from(&input)).unwrap();
-                    });
-
-                    bail!("Running validation `{}` failed.", name.fg(Color::Green));
-                }
-                Err(e) => bail!(e),
+    /// Adds a validator, which runs after the ones already registered.
+    pub fn register<V: Validator + 'static>(mut self, validator: V) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    /// Replaces the validator with the same name as `validator`, or adds it if there is none.
+    pub fn replace<V: Validator + 'static>(mut self, validator: V) -> Self {
+        match self
+            .validators
+            .iter()
+            .position(|v| v.name() == validator.name())
+        {
+            Some(i) => self.validators[i] = Box::new(validator),
+            None => self.validators.push(Box::new(validator)),
+        }
+
+        self
+    }
+
+    pub fn validators(&self) -> impl Iterator<Item = &dyn Validator> {
+        self.validators.iter().map(|v| v.as_ref())
+    }
+
+    /// Runs every validator the ledger didn't turn off, collecting the problems they find.
+    pub fn run(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = vec![];
+
+        for validator in self.validators() {
+            if context.options.rules.get(validator.name()) == Some(&None) {
+                continue;
             }
+
+            let found = validator.validate(filename, ledger, context)?;
+            diagnostics.extend(configure(validator, found, &context.options));
         }
 
-        Ok(())
+        diagnostics.extend(unknown_rules(filename, self.validators(), context));
+
+        Ok(diagnostics)
     }
 }
 
-type Validator = fn(&Ledger) -> Result<(), ValidationError>;
+/// Credits and debits of the whole ledger add up to the same amount.
+pub struct LedgerBalance;
+
+impl Validator for LedgerBalance {
+    fn name(&self) -> &'static str {
+        "unbalanced_ledger"
+    }
 
-pub static ALL_VALIDATORS: &[(&'static str, Validator)] = &[
-    (
-        "validate that credits and debits balance",
-        validate_credits_and_debits_balance,
-    ),
-    (
-        "validate that all isolated transactions are properly balanced",
-        validate_all_isolated_transactions_balance,
-    ),
-];
+    fn description(&self) -> &'static str {
+        "validate that credits and debits balance"
+    }
 
-fn validate_credits_and_debits_balance(ledger: &Ledger) -> Result<(), ValidationError> {
-    let credit_sum: u64 = ledger
-        .credits()?
-        .column("ledger.amount")?
-        .sum()
-        .unwrap_or(0);
-    let debit_sum: u64 = ledger.debits()?.column("ledger.amount")?.sum().unwrap_or(0);
+    fn code(&self) -> &'static str {
+        codes::UNBALANCED_LEDGER
+    }
 
-    if credit_sum == debit_sum {
-        return Ok(());
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = validate_credits_and_debits_balance(ledger, &context.options)?;
+        Ok(into_diagnostics(traces, self.code(), filename))
     }
+}
 
-    Err(ValidationError::WithTrace(vec![ValidationTrace {
-        message: "Budget does not balance".to_string(),
-        details:
-            "In a double-entry accounting system, all credits and debits should balance in the end."
-                .into(),
-        span: None,
-        found: Some(format!("{:.1$}", credit_sum as i64 - debit_sum as i64, 2)),
-        expected: Some(format!("0.0")),
-    }]))
+/// Credits and debits of each transaction add up to the same amount.
+pub struct TransactionBalance;
+
+impl Validator for TransactionBalance {
+    fn name(&self) -> &'static str {
+        "unbalanced_transaction"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that all isolated transactions are properly balanced"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::UNBALANCED_TRANSACTION
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = validate_all_isolated_transactions_balance(ledger, context)?;
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// Movements only use currencies their accounts were opened with.
+pub struct AllowedCurrencies;
+
+impl Validator for AllowedCurrencies {
+    fn name(&self) -> &'static str {
+        "currency_not_allowed"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that movements only use currencies allowed by their accounts"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::CURRENCY_NOT_ALLOWED
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = validate_allowed_currencies(ledger, context)?;
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// Files pointed to by `document` ops exist.
+pub struct DocumentsExist;
+
+impl Validator for DocumentsExist {
+    fn name(&self) -> &'static str {
+        "missing_document"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that all documents exist"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::MISSING_DOCUMENT
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        _: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = validate_documents_exist(filename, context);
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// `balance` ops match the balances computed from the ledger.
+pub struct BalanceAssertions;
+
+impl Validator for BalanceAssertions {
+    fn name(&self) -> &'static str {
+        "balance_mismatch"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that balance statements match the ledger"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::BALANCE_MISMATCH
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        Ok(ledger.validate_balances(
+            filename,
+            context.balance_verifications.clone(),
+            &context.options,
+        )?)
+    }
+}
+
+/// Every `assert` op holds for the ledger.
+pub struct Assertions;
+
+impl Validator for Assertions {
+    fn name(&self) -> &'static str {
+        "failed_assertion"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that assert statements hold for the ledger"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::ASSERTION_FAILED
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = vec![];
+
+        for assertion in context.assertions.iter() {
+            let value = assertion.evaluate(ledger, &context.transaction_metadata)?;
+
+            diagnostics.extend(assertion.check(filename, value, &context.options));
+        }
+
+        Ok(diagnostics)
+    }
+}
+
+/// No transaction looks like one written before it, which usually means it was imported twice.
+pub struct DuplicateTransactions;
+
+impl Validator for DuplicateTransactions {
+    fn name(&self) -> &'static str {
+        "duplicate_transaction"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that no transaction is a likely duplicate"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::DUPLICATE_TRANSACTION
+    }
+
+    fn severity(&self, _: &LedgerOptions) -> Severity {
+        Severity::Warning
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = validate_no_duplicates(ledger, context)?;
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// Ops are written in date order.
+pub struct DateOrder;
+
+impl Validator for DateOrder {
+    fn name(&self) -> &'static str {
+        "out_of_order"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that ops are written in date order"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::OUT_OF_ORDER
+    }
+
+    fn severity(&self, options: &LedgerOptions) -> Severity {
+        if options.strict_date_order {
+            Severity::Error
+        } else {
+            Severity::Warning
+        }
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        _: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = context
+            .dated_ops
+            .windows(2)
+            .filter_map(|pair| check_date_order(&pair[0], &pair[1]))
+            .collect();
+
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// No op is dated after `today`.
+pub struct FutureDates {
+    pub today: NaiveDate,
+}
+
+impl FutureDates {
+    pub fn new(today: NaiveDate) -> Self {
+        Self { today }
+    }
+}
+
+impl Default for FutureDates {
+    /// Checks against the current day on the local clock.
+    fn default() -> Self {
+        Self::new(Local::today().naive_local())
+    }
+}
+
+impl Validator for FutureDates {
+    fn name(&self) -> &'static str {
+        "future_date"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that no op is dated in the future"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::FUTURE_DATE
+    }
+
+    fn severity(&self, _: &LedgerOptions) -> Severity {
+        Severity::Warning
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        _: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = context
+            .dated_ops
+            .iter()
+            .filter_map(|op| check_future_date(op, self.today))
+            .collect();
+
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
 }
 
-fn validate_all_isolated_transactions_balance(ledger: &Ledger) -> Result<(), ValidationError> {
-    let mut df = ledger.all()?;
+/// No account balance goes to the opposite of its normal side, unless its `open` allows it.
+pub struct AbnormalBalances;
+
+impl Validator for AbnormalBalances {
+    fn name(&self) -> &'static str {
+        "abnormal_balance"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that account balances stay on their normal side"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::ABNORMAL_BALANCE
+    }
+
+    fn severity(&self, _: &LedgerOptions) -> Severity {
+        Severity::Warning
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let mut balances = AccountBalances::default();
 
-    let credit_factor = df
-        .column("ledger.is_credit")?
-        .bool()?
-        .branch_apply_cast_numeric_no_null::<_, Float64Type>(|x| {
-            if x == Some(true) {
-                1.0
+        for row in rows(ledger)? {
+            let account = match row.account.parse::<Account>() {
+                Ok(account) => account,
+                Err(_) => continue,
+            };
+            let kind = if row.is_credit {
+                MovementKind::Credit
             } else {
-                -1.0
-            }
-        })
-        .into_series();
+                MovementKind::Debit
+            };
+
+            balances.add_movement(&account, row.currency, row.date, kind, row.amount, row.span);
+        }
+
+        let traces = balances.traces(&context.opens, &context.options);
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
 
-    df.replace(
-        "ledger.amount",
-        df.column("ledger.amount")?.multiply(&credit_factor)?,
-    )?;
+/// Every opened account has movements.
+pub struct UnusedAccounts;
 
-    let grouped = df.groupby("ledger.parent_id")?;
+impl Validator for UnusedAccounts {
+    fn name(&self) -> &'static str {
+        "unused_account"
+    }
 
-    let sums = grouped.clone().sum()?;
-    let mins = grouped.clone().min()?;
-    let maxes = grouped.clone().max()?;
+    fn description(&self) -> &'static str {
+        "validate that every opened account is used"
+    }
 
-    let amount_sum = sums.column("ledger.amount_sum")?;
-    let span_start = mins.column("ledger.span_start_min")?;
-    let span_end = maxes.column("ledger.span_end_max")?;
+    fn code(&self) -> &'static str {
+        codes::UNUSED_ACCOUNT
+    }
 
-    let df = DataFrame::new(vec![
-        sums.column("ledger.parent_id")?.clone(),
-        amount_sum.clone(),
-        span_start.clone(),
-        span_end.clone(),
-    ])?;
+    fn severity(&self, _: &LedgerOptions) -> Severity {
+        Severity::Warning
+    }
 
-    let unbalanced = df.column("ledger.amount_sum")?.f64()?.not_equal(0.0);
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let used = rows(ledger)?
+            .into_iter()
+            .map(|row| row.account)
+            .collect::<BTreeSet<_>>();
 
-    let result = df.filter(&unbalanced)?;
+        let traces = unused(&context.opens, &used)
+            .into_iter()
+            .map(ValidationTrace::unused_account)
+            .collect();
 
-    if result.shape().0 == 0 {
-        return Ok(());
+        Ok(into_diagnostics(traces, self.code(), filename))
     }
+}
 
-    let mut errors = vec![];
+/// Every account with movements is opened, by itself or by an account above it.
+pub struct UnopenedAccounts;
+
+impl Validator for UnopenedAccounts {
+    fn name(&self) -> &'static str {
+        "unopened_account"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that every account with movements is opened"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::UNOPENED_ACCOUNT
+    }
+
+    fn severity(&self, _: &LedgerOptions) -> Severity {
+        Severity::Warning
+    }
 
-    let to_number = |v: &AnyValue| -> Option<u64> {
-        match v {
-            AnyValue::UInt64(v) => Some(*v),
-            _ => None,
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let ops = &context.dated_ops;
+        let mut uses = AccountUses::default();
+
+        for row in rows(ledger)? {
+            // Ops are kept in the order they are written, so a movement belongs to the last one
+            // that starts before it.
+            let op = match ops.partition_point(|(_, span)| span.start <= row.span.start) {
+                0 => continue,
+                i => ops[i - 1].1.clone(),
+            };
+
+            uses.add(
+                row.account,
+                AccountUse {
+                    date: row.date,
+                    name: row.historical_account,
+                    span: row.span,
+                    op,
+                },
+            );
         }
-    };
 
-    for i in 0..result.shape().0 {
-        let item = result.get(i).unwrap();
+        let traces = uses.traces(&context.opens);
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
 
-        errors.push(ValidationTrace {
-            message: "Transaction does not balance".into(),
-            details: "Inside a transaction, all debits and credits must balance in the end."
-                .to_string(),
-            found: Some(format!("{:.1$}", item.get(1).unwrap(), 2)),
-            expected: Some("0.0".to_string()),
-            span: item.get(2).and_then(to_number).and_then(|start| {
-                item.get(3)
-                    .and_then(to_number)
-                    .map(|end| (start as usize)..((end - 1) as usize))
-            }),
-        })
+/// Account names follow the rules set with `account_categories.*`, `account_pattern.*` and
+/// `max_account_depth` options.
+pub struct AccountNaming;
+
+impl Validator for AccountNaming {
+    fn name(&self) -> &'static str {
+        "account_naming"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that account names follow the ledger's naming rules"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::ACCOUNT_NAMING
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        context: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let mut names = AccountNames::default();
+
+        for opening in context.opens.iter() {
+            names.add(opening.account.to_string(), &opening.span);
+        }
+
+        for row in rows(ledger)? {
+            names.add(row.account, &row.span);
+        }
+
+        let traces = names.traces(&context.options);
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// Every transaction has more than one movement.
+pub struct SingleMovements;
+
+impl Validator for SingleMovements {
+    fn name(&self) -> &'static str {
+        "single_movement"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that transactions have at least two movements"
     }
 
-    Err(ValidationError::WithTrace(errors))
+    fn code(&self) -> &'static str {
+        codes::SINGLE_MOVEMENT
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        _: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = transactions(ledger)?
+            .values()
+            .filter_map(|rows| {
+                let spans = rows.iter().map(|r| r.span.clone()).collect::<Vec<_>>();
+                check_movement_count(&spans)
+            })
+            .collect();
+
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// No movement has an amount of zero.
+pub struct ZeroAmounts;
+
+impl Validator for ZeroAmounts {
+    fn name(&self) -> &'static str {
+        "zero_amount"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that no movement has an amount of zero"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::ZERO_AMOUNT
+    }
+
+    fn severity(&self, _: &LedgerOptions) -> Severity {
+        Severity::Warning
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        _: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = rows(ledger)?
+            .iter()
+            .filter_map(|row| check_zero_amount(&row.amount, &row.span))
+            .collect();
+
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// Every transaction has both debits and credits.
+pub struct OneSidedTransactions;
+
+impl Validator for OneSidedTransactions {
+    fn name(&self) -> &'static str {
+        "one_sided_transaction"
+    }
+
+    fn description(&self) -> &'static str {
+        "validate that transactions have both debits and credits"
+    }
+
+    fn code(&self) -> &'static str {
+        codes::ONE_SIDED_TRANSACTION
+    }
+
+    fn validate(
+        &self,
+        filename: &Path,
+        ledger: &Ledger,
+        _: &LedgerContext,
+    ) -> Result<Vec<Diagnostic>> {
+        let traces = transactions(ledger)?
+            .values()
+            .filter_map(|rows| {
+                let movements = rows
+                    .iter()
+                    .map(|r| {
+                        let kind = if r.is_credit {
+                            MovementKind::Credit
+                        } else {
+                            MovementKind::Debit
+                        };
+
+                        (kind, r.span.clone())
+                    })
+                    .collect::<Vec<_>>();
+
+                check_sides(&movements)
+            })
+            .collect();
+
+        Ok(into_diagnostics(traces, self.code(), filename))
+    }
+}
+
+/// Reports a transaction with a single movement, given the spans of its movements.
+pub(crate) fn check_movement_count(movements: &[Span]) -> Option<ValidationTrace> {
+    match movements {
+        [movement] => Some(ValidationTrace {
+            message: "Transaction has a single movement".into(),
+            details: "Money moves from some accounts to others, so a transaction needs at least \
+                one credit and one debit."
+                .into(),
+            span: Some(movement.clone()),
+            found: None,
+            expected: None,
+            related: vec![],
+            fix: None,
+        }),
+        _ => None,
+    }
+}
+
+pub(crate) fn check_zero_amount(amount: &BigRational, span: &Span) -> Option<ValidationTrace> {
+    if !amount.is_zero() {
+        return None;
+    }
+
+    Some(ValidationTrace {
+        message: "Movement has an amount of zero".into(),
+        details: "This movement doesn't change any balance, so the amount may be missing.".into(),
+        span: Some(span.clone()),
+        found: None,
+        expected: None,
+        related: vec![],
+        fix: None,
+    })
+}
+
+/// Reports a transaction with more than one movement where all of them go to the same side.
+pub(crate) fn check_sides(movements: &[(MovementKind, Span)]) -> Option<ValidationTrace> {
+    let (first, last) = match movements {
+        [first, .., last] => (first, last),
+        _ => return None,
+    };
+
+    if movements.iter().any(|(kind, _)| *kind != first.0) {
+        return None;
+    }
+
+    let side = match first.0 {
+        MovementKind::Credit => "credit",
+        MovementKind::Debit => "debit",
+    };
+
+    Some(ValidationTrace {
+        message: format!("Every movement of the transaction is a {}", side),
+        details: "A transaction needs at least one credit and one debit, so the sign of one of \
+            these movements may be wrong."
+            .into(),
+        span: Some(first.1.start..last.1.end),
+        found: None,
+        expected: None,
+        related: vec![],
+        fix: None,
+    })
+}
+
+/// Reports `op` when it is dated before `previous`, the op written right before it.
+pub(crate) fn check_date_order(
+    previous: &Spanned<NaiveDate>,
+    op: &Spanned<NaiveDate>,
+) -> Option<ValidationTrace> {
+    if op.0 >= previous.0 {
+        return None;
+    }
+
+    Some(ValidationTrace {
+        message: format!(
+            "Op dated {} is written after one dated {}",
+            op.0, previous.0
+        ),
+        details: "Ops are expected in date order, so this date may be a typo.".into(),
+        span: Some(op.1.clone()),
+        found: None,
+        expected: None,
+        related: vec![(previous.1.clone(), "Previous op written here".to_string())],
+        fix: None,
+    })
+}
+
+pub(crate) fn check_future_date(
+    op: &Spanned<NaiveDate>,
+    today: NaiveDate,
+) -> Option<ValidationTrace> {
+    if op.0 <= today {
+        return None;
+    }
+
+    Some(ValidationTrace {
+        message: format!("Op is dated in the future, after {}", today),
+        details: "Entries are written once they happen, so this date may be a typo.".into(),
+        span: Some(op.1.clone()),
+        found: Some(op.0.to_string()),
+        expected: None,
+        related: vec![],
+        fix: None,
+    })
+}
+
+pub(crate) fn into_diagnostics(
+    traces: Vec<ValidationTrace>,
+    code: &'static str,
+    filename: &Path,
+) -> Vec<Diagnostic> {
+    traces
+        .into_iter()
+        .map(|t| t.into_diagnostic(code, filename))
+        .collect()
+}
+
+/// What the movements of one day add up to in one currency.
+#[derive(Debug, Clone)]
+struct Day {
+    /// Credits minus debits.
+    difference: BigRational,
+    /// The first transaction of the day that doesn't balance on its own.
+    unbalanced: Option<Span>,
+    first: Span,
+}
+
+/// Credits minus debits of each day, by currency, used to find the day the books stop balancing.
+///
+/// Amounts are added as exact rationals, so no cents are lost no matter how many movements there
+/// are.
+#[derive(Debug, Default)]
+pub(crate) struct DailyBalances(BTreeMap<String, BTreeMap<NaiveDate, Day>>);
+
+impl DailyBalances {
+    /// Adds the movements of one transaction, as signed amounts where credits are positive.
+    pub(crate) fn add_transaction<I>(&mut self, date: NaiveDate, movements: I)
+    where
+        I: IntoIterator<Item = (String, BigRational, Span)>,
+    {
+        let mut sums: BTreeMap<String, (BigRational, Span)> = BTreeMap::new();
+
+        for (currency, amount, span) in movements {
+            let (sum, whole) = sums
+                .entry(currency)
+                .or_insert_with(|| (BigRational::zero(), span.clone()));
+
+            *sum += amount;
+            *whole = whole.start.min(span.start)..whole.end.max(span.end);
+        }
+
+        for (currency, (sum, span)) in sums {
+            let day = self
+                .0
+                .entry(currency)
+                .or_default()
+                .entry(date)
+                .or_insert_with(|| Day {
+                    difference: BigRational::zero(),
+                    unbalanced: None,
+                    first: span.clone(),
+                });
+
+            if !sum.is_zero() && day.unbalanced.is_none() {
+                day.unbalanced = Some(span);
+            }
+
+            day.difference += sum;
+        }
+    }
+
+    /// One trace per currency that goes out of balance, pointing at the first day it does.
+    pub(crate) fn traces(&self, options: &LedgerOptions) -> Vec<ValidationTrace> {
+        let mut traces = vec![];
+
+        for (currency, days) in self.0.iter() {
+            let mut running = BigRational::zero();
+
+            for (date, day) in days.iter() {
+                running += &day.difference;
+
+                if options.exceeds_tolerance(&running) {
+                    let span = day.unbalanced.clone().unwrap_or_else(|| day.first.clone());
+
+                    traces.push(ValidationTrace::unbalanced_ledger(
+                        currency,
+                        *date,
+                        &running,
+                        Some(span),
+                        options,
+                    ));
+                    break;
+                }
+            }
+        }
+
+        traces
+    }
+}
+
+/// What the movements of one day add up to in one account and currency.
+#[derive(Debug)]
+struct AccountDay {
+    /// Amounts signed so that the normal side of the account is positive.
+    sum: BigRational,
+    /// The first movement of the day towards the abnormal side.
+    abnormal: Option<Span>,
+}
+
+/// The movements of each day, by account and currency, used to find when the balance of an
+/// account goes to its abnormal side.
+#[derive(Debug, Default)]
+pub(crate) struct AccountBalances(
+    BTreeMap<(String, String), (Account, BTreeMap<NaiveDate, AccountDay>)>,
+);
+
+impl AccountBalances {
+    pub(crate) fn add_movement(
+        &mut self,
+        account: &Account,
+        currency: String,
+        date: NaiveDate,
+        kind: MovementKind,
+        amount: BigRational,
+        span: Span,
+    ) {
+        let signed = amount * BigRational::from_integer(account.signed_factor(kind).into());
+
+        let day = self
+            .0
+            .entry((account.to_string(), currency))
+            .or_insert_with(|| (account.clone(), BTreeMap::new()))
+            .1
+            .entry(date)
+            .or_insert_with(|| AccountDay {
+                sum: BigRational::zero(),
+                abnormal: None,
+            });
+
+        if signed.is_negative() && day.abnormal.is_none() {
+            day.abnormal = Some(span);
+        }
+
+        day.sum += signed;
+    }
+
+    /// One trace per account and currency whose balance ends a day on its abnormal side,
+    /// pointing at the first day it does. Accounts opened with an `abnormal_balance` key, or
+    /// below one that was, are skipped.
+    pub(crate) fn traces(
+        &self,
+        opens: &[AccountOpening],
+        options: &LedgerOptions,
+    ) -> Vec<ValidationTrace> {
+        let allowed = |name: &str| {
+            opens.iter().any(|o| {
+                o.get("abnormal_balance").is_some()
+                    && is_same_or_child(name, &o.account.to_string())
+            })
+        };
+
+        let mut traces = vec![];
+
+        for ((name, currency), (account, days)) in self.0.iter() {
+            if allowed(name) {
+                continue;
+            }
+
+            let mut running = BigRational::zero();
+
+            for (date, day) in days.iter() {
+                running += &day.sum;
+
+                if running.is_negative() && options.exceeds_tolerance(&running) {
+                    traces.push(ValidationTrace::abnormal_balance(
+                        account,
+                        currency,
+                        *date,
+                        &running,
+                        day.abnormal.clone(),
+                        options,
+                    ));
+                    break;
+                }
+            }
+        }
+
+        traces.sort_by_key(|t| t.span.as_ref().map(|s| s.start));
+        traces
+    }
+}
+
+/// Every account name in the ledger, along with where it is first written, to check them against
+/// the ledger's naming rules.
+#[derive(Debug, Default)]
+pub(crate) struct AccountNames(BTreeMap<String, Span>);
+
+impl AccountNames {
+    pub(crate) fn add(&mut self, name: String, span: &Span) {
+        let first = self.0.entry(name).or_insert_with(|| span.clone());
+
+        if span.start < first.start {
+            *first = span.clone();
+        }
+    }
+
+    pub(crate) fn traces(&self, options: &LedgerOptions) -> Vec<ValidationTrace> {
+        let patterns = options
+            .account_patterns
+            .iter()
+            .filter_map(|(root, pattern)| Some((root.as_str(), Regex::new(pattern).ok()?)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut traces = vec![];
+
+        for (name, span) in self.0.iter() {
+            let parts = name.split(':').collect::<Vec<_>>();
+            let root = parts[0];
+
+            if let Some(max) = options.max_account_depth {
+                if parts.len() > max as usize {
+                    traces.push(ValidationTrace::account_naming(
+                        format!("Account `{}` is nested deeper than {} levels", name, max),
+                        "max_account_depth",
+                        None,
+                        span,
+                    ));
+                }
+            }
+
+            if let Some(categories) = options.account_categories.get(root) {
+                if parts.len() > 1 && !categories.iter().any(|c| c == parts[1]) {
+                    traces.push(ValidationTrace::account_naming(
+                        format!("`{}` is not a category allowed under `{}`", parts[1], root),
+                        &format!("account_categories.{}", root),
+                        Some(categories.join(" ")),
+                        span,
+                    ));
+                }
+            }
+
+            if let Some(pattern) = patterns.get(root) {
+                if !pattern.is_match(name) {
+                    traces.push(ValidationTrace::account_naming(
+                        format!("Account `{}` does not match the naming pattern", name),
+                        &format!("account_pattern.{}", root),
+                        Some(pattern.to_string()),
+                        span,
+                    ));
+                }
+            }
+        }
+
+        traces.sort_by_key(|t| t.span.as_ref().map(|s| s.start));
+        traces
+    }
+}
+
+/// Where an account is first used.
+#[derive(Debug, Clone)]
+pub(crate) struct AccountUse {
+    pub(crate) date: NaiveDate,
+    /// The name the account had on `date`.
+    pub(crate) name: String,
+    pub(crate) span: Span,
+    /// The op with the movement, before which an `open` for the account can be written.
+    pub(crate) op: Span,
+}
+
+/// The first use of each account, by its current name, used to find the accounts that were never
+/// opened.
+#[derive(Debug, Default)]
+pub(crate) struct AccountUses(BTreeMap<String, AccountUse>);
+
+impl AccountUses {
+    pub(crate) fn add(&mut self, account: String, current: AccountUse) {
+        let first = self.0.entry(account).or_insert_with(|| current.clone());
+
+        if current.span.start < first.span.start {
+            *first = current;
+        }
+    }
+
+    /// One trace per account that is not opened, neither itself nor any account above it.
+    pub(crate) fn traces(&self, opens: &[AccountOpening]) -> Vec<ValidationTrace> {
+        let opened = opens
+            .iter()
+            .map(|o| o.account.to_string())
+            .collect::<BTreeSet<_>>();
+
+        let is_opened = |account: &str| {
+            account
+                .match_indices(':')
+                .map(|(i, _)| &account[..i])
+                .chain(std::iter::once(account))
+                .any(|name| opened.contains(name))
+        };
+
+        let mut traces = self
+            .0
+            .iter()
+            .filter(|(account, _)| !is_opened(account))
+            .map(|(account, first)| ValidationTrace::unopened_account(account, first))
+            .collect::<Vec<_>>();
+
+        traces.sort_by_key(|t| t.span.as_ref().map(|s| s.start));
+        traces
+    }
+}
+
+/// A single movement of the ledger, as read from its columns.
+struct Row {
+    parent: u64,
+    date: NaiveDate,
+    description: String,
+    account: String,
+    /// The name `account` had on `date`.
+    historical_account: String,
+    currency: String,
+    amount: BigRational,
+    is_credit: bool,
+    span: Span,
+}
+
+impl Row {
+    fn movement(&self) -> Option<Spanned<Movement>> {
+        let kind = if self.is_credit {
+            MovementKind::Credit
+        } else {
+            MovementKind::Debit
+        };
+        let money = Money::new(self.amount.clone(), self.currency.clone());
+
+        Some((
+            Movement(kind, money, self.account.parse().ok()?),
+            self.span.clone(),
+        ))
+    }
+}
+
+/// Every movement of the ledger, in the order they were written.
+fn rows(ledger: &Ledger) -> Result<Vec<Row>> {
+    let df = ledger.all()?;
+
+    let parents = df.column("ledger.parent_id")?.u64()?;
+    let dates = df.column("ledger.date")?.date()?.as_date_iter();
+    let descriptions = df.column("ledger.description")?.utf8()?;
+    let accounts = df.column("ledger.account_name")?.utf8()?;
+    let historical_accounts = df.column("ledger.historical_account_name")?.utf8()?;
+    let currencies = df.column("ledger.currency")?.utf8()?;
+    let amounts = df.column("ledger.exact_amount")?.utf8()?;
+    let is_credit = df.column("ledger.is_credit")?.bool()?;
+    let span_start = df.column("ledger.span_start")?.u64()?;
+    let span_end = df.column("ledger.span_end")?.u64()?;
+
+    let mut rows = vec![];
+
+    for (((((((parent, date), description), account), currency), amount), credit), span) in parents
+        .into_iter()
+        .zip(dates)
+        .zip(descriptions)
+        .zip(accounts.into_iter().zip(historical_accounts))
+        .zip(currencies)
+        .zip(amounts)
+        .zip(is_credit)
+        .zip(span_start.into_iter().zip(span_end))
+    {
+        // Every movement belongs to a transaction, so a missing value means the ledger is broken,
+        // and validating only part of it would report problems that are not there.
+        let row = match (
+            parent,
+            date,
+            description,
+            account,
+            currency,
+            amount,
+            credit,
+            span,
+        ) {
+            (
+                Some(parent),
+                Some(date),
+                Some(description),
+                (Some(account), Some(historical_account)),
+                Some(currency),
+                Some(amount),
+                Some(is_credit),
+                (Some(start), Some(end)),
+            ) => Row {
+                parent,
+                date,
+                description: description.to_string(),
+                account: account.to_string(),
+                historical_account: historical_account.to_string(),
+                currency: currency.to_string(),
+                amount: amount
+                    .parse()
+                    .map_err(|_| anyhow!("`{}` is not a valid amount", amount))?,
+                is_credit,
+                span: start as usize..end as usize,
+            },
+            _ => bail!("The ledger has a movement with missing values"),
+        };
+
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+/// The movements of each transaction, by the id of the transaction.
+fn transactions(ledger: &Ledger) -> Result<BTreeMap<u64, Vec<Row>>> {
+    let mut transactions: BTreeMap<u64, Vec<Row>> = BTreeMap::new();
+
+    for row in rows(ledger)? {
+        transactions.entry(row.parent).or_default().push(row);
+    }
+
+    Ok(transactions)
+}
+
+fn validate_credits_and_debits_balance(
+    ledger: &Ledger,
+    options: &LedgerOptions,
+) -> Result<Vec<ValidationTrace>> {
+    // Transaction ids grow in the order they are written.
+    let mut transactions: BTreeMap<u64, (NaiveDate, Vec<_>)> = BTreeMap::new();
+
+    for row in rows(ledger)? {
+        let signed = if row.is_credit {
+            row.amount
+        } else {
+            -row.amount
+        };
+
+        transactions
+            .entry(row.parent)
+            .or_insert_with(|| (row.date, vec![]))
+            .1
+            .push((row.currency, signed, row.span));
+    }
+
+    let mut balances = DailyBalances::default();
+
+    for (date, movements) in transactions.into_values() {
+        balances.add_transaction(date, movements);
+    }
+
+    Ok(balances.traces(options))
+}
+
+/// A transaction as compared when looking for duplicates.
+pub(crate) struct Candidate {
+    date: NaiveDate,
+    description: String,
+    /// Movements as `(is_credit, account, currency, amount)`, sorted so that order doesn't matter.
+    movements: Vec<(bool, String, String, BigRational)>,
+    span: Span,
+    /// Whether the transaction has a `repeated` key, which means it is meant to look the same as
+    /// another one.
+    repeated: bool,
+}
+
+/// Lowercase words of `text`, without punctuation.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Whether two descriptions are close enough to be written for the same thing, allowing for
+/// changes in case, punctuation and a few characters.
+fn similar_descriptions(a: &str, b: &str) -> bool {
+    let (a, b) = (
+        normalize(a).chars().collect::<Vec<_>>(),
+        normalize(b).chars().collect::<Vec<_>>(),
+    );
+
+    // Levenshtein distance, keeping a single row of the table.
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+
+    let longest = a.len().max(b.len());
+    longest == 0 || row[b.len()] * 5 <= longest
+}
+
+fn validate_no_duplicates(
+    ledger: &Ledger,
+    context: &LedgerContext,
+) -> Result<Vec<ValidationTrace>> {
+    let mut transactions: BTreeMap<u64, Candidate> = BTreeMap::new();
+
+    for row in rows(ledger)? {
+        let repeated = context
+            .transaction_metadata
+            .get(&row.parent)
+            .is_some_and(|m| m.iter().any(|(key, _)| key == "repeated"));
+
+        let candidate = transactions.entry(row.parent).or_insert_with(|| Candidate {
+            date: row.date,
+            description: row.description,
+            movements: vec![],
+            span: row.span.clone(),
+            repeated,
+        });
+
+        candidate.span =
+            candidate.span.start.min(row.span.start)..candidate.span.end.max(row.span.end);
+        candidate
+            .movements
+            .push((row.is_credit, row.account, row.currency, row.amount));
+    }
+
+    // Only transactions with the same movements can be duplicates of each other.
+    let mut groups: HashMap<Vec<_>, Vec<&Candidate>> = HashMap::new();
+
+    for candidate in transactions.values_mut() {
+        candidate.movements.sort();
+    }
+
+    for candidate in transactions.values().filter(|c| !c.repeated) {
+        groups
+            .entry(candidate.movements.clone())
+            .or_default()
+            .push(candidate);
+    }
+
+    let window = context.options.duplicate_window as i64;
+    let mut traces = vec![];
+
+    for group in groups.values() {
+        for (i, later) in group.iter().enumerate() {
+            let earlier = group[..i].iter().find(|earlier| {
+                (later.date - earlier.date).num_days().abs() <= window
+                    && similar_descriptions(&earlier.description, &later.description)
+            });
+
+            if let Some(earlier) = earlier {
+                traces.push((
+                    later.span.start,
+                    ValidationTrace::duplicate_transaction(earlier, later),
+                ));
+            }
+        }
+    }
+
+    traces.sort_by_key(|(start, _)| *start);
+
+    Ok(traces.into_iter().map(|(_, trace)| trace).collect())
+}
+
+fn validate_all_isolated_transactions_balance(
+    ledger: &Ledger,
+    context: &LedgerContext,
+) -> Result<Vec<ValidationTrace>> {
+    let mut errors = vec![];
+
+    for rows in transactions(ledger)?.values() {
+        let sum = rows
+            .iter()
+            .map(|row| match row.is_credit {
+                true => row.amount.clone(),
+                false => -row.amount.clone(),
+            })
+            .sum::<BigRational>();
+
+        if !context.options.exceeds_tolerance(&sum) {
+            continue;
+        }
+
+        let start = rows.iter().map(|row| row.span.start).min();
+        let end = rows.iter().map(|row| row.span.end).max();
+
+        errors.push(ValidationTrace::unbalanced_transaction(
+            sum.to_f64().unwrap_or(f64::NAN),
+            start.zip(end).map(|(start, end)| start..end - 1),
+            &rows.iter().filter_map(Row::movement).collect::<Vec<_>>(),
+        ));
+    }
+
+    Ok(errors)
+}
+
+fn validate_allowed_currencies(
+    ledger: &Ledger,
+    context: &LedgerContext,
+) -> Result<Vec<ValidationTrace>> {
+    let df = ledger.all()?;
+
+    let accounts = df.column("ledger.account_name")?.utf8()?;
+    let currencies = df.column("ledger.currency")?.utf8()?;
+    let span_start = df.column("ledger.span_start")?.u64()?;
+    let span_end = df.column("ledger.span_end")?.u64()?;
+
+    let mut errors = vec![];
+
+    for (((account, currency), start), end) in accounts
+        .into_iter()
+        .zip(currencies)
+        .zip(span_start)
+        .zip(span_end)
+    {
+        let (account, currency) = match (account, currency) {
+            (Some(account), Some(currency)) => (account, Currency::from(currency)),
+            _ => continue,
+        };
+
+        let opening = match find_opening(&context.opens, account) {
+            Some(opening) if !opening.allows(&currency) => opening,
+            _ => continue,
+        };
+
+        errors.push(ValidationTrace::currency_not_allowed(
+            account,
+            currency,
+            start.zip(end).map(|(s, e)| (s as usize)..(e as usize)),
+            opening,
+        ));
+    }
+
+    Ok(errors)
+}
+
+fn validate_documents_exist(filename: &Path, context: &LedgerContext) -> Vec<ValidationTrace> {
+    let root = filename.parent().unwrap_or_else(|| Path::new("."));
+
+    context
+        .documents
+        .iter()
+        .filter(|d| !root.join(&d.path).exists())
+        .map(ValidationTrace::missing_document)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{compute_program, syntax::parse_string};
+
+    /// Reports every account opened without currencies.
+    struct AnyCurrency;
+
+    impl Validator for AnyCurrency {
+        fn name(&self) -> &'static str {
+            "any_currency"
+        }
+
+        fn description(&self) -> &'static str {
+            "validate that accounts restrict their currencies"
+        }
+
+        fn code(&self) -> &'static str {
+            "X0001"
+        }
+
+        fn severity(&self, _: &LedgerOptions) -> Severity {
+            Severity::Note
+        }
+
+        fn validate(
+            &self,
+            filename: &Path,
+            _: &Ledger,
+            context: &LedgerContext,
+        ) -> Result<Vec<Diagnostic>> {
+            Ok(context
+                .opens
+                .iter()
+                .filter(|o| o.currencies.is_empty())
+                .map(|_| Diagnostic::error(self.code(), "No currencies").in_file(filename))
+                .collect())
+        }
+    }
+
+    fn run(runner: &ValidationRunner, input: &str) -> Result<Vec<(&'static str, Severity)>> {
+        let filename = Path::new("books.hta");
+        let (ledger, context) = compute_program(parse_string(filename, input)?)?;
+
+        Ok(runner
+            .run(filename, &ledger, &context)?
+            .into_iter()
+            .map(|d| (d.code, d.severity))
+            .collect())
+    }
+
+    const INPUT: &str = r#"2020-01-01 open assets:cash BRL
+2020-01-01 open equity:initial_import
+2020-01-02 transaction "Unbalanced"
+  > 10 BRL equity:initial_import
+  < 9 BRL assets:cash
+2020-01-02 document assets:cash "missing.pdf"
+"#;
+
+    #[test]
+    fn test_runs_every_validator() -> Result<()> {
+        assert_eq!(
+            run(&ValidationRunner::default(), INPUT)?,
+            vec![
+                (codes::UNBALANCED_LEDGER, Severity::Error),
+                (codes::UNBALANCED_TRANSACTION, Severity::Error),
+                (codes::MISSING_DOCUMENT, Severity::Error),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules_are_configured_by_the_ledger() -> Result<()> {
+        let input = format!(
+            "{}{}",
+            r#"option "rule.unbalanced_ledger" "off"
+option "rule.unbalanced_transaction" "warning"
+option "rule.any_currency" "error"
+"#,
+            INPUT
+        );
+        let runner = ValidationRunner::default().register(AnyCurrency);
+
+        assert_eq!(
+            run(&runner, &input)?,
+            vec![
+                (codes::UNBALANCED_TRANSACTION, Severity::Warning),
+                (codes::MISSING_DOCUMENT, Severity::Error),
+                ("X0001", Severity::Error),
+            ]
+        );
+        assert_eq!(
+            run(&ValidationRunner::empty().register(AnyCurrency), INPUT)?,
+            vec![("X0001", Severity::Note)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_rules_are_reported() -> Result<()> {
+        let input = r#"option "rule.duplicate_transactions" "off"
+option "rule.any_currency" "warning"
+"#;
+        let filename = Path::new("books.hta");
+        let (ledger, context) = compute_program(parse_string(filename, input)?)?;
+        let diagnostics = ValidationRunner::default().run(filename, &ledger, &context)?;
+
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.code == codes::UNKNOWN_RULE && d.severity == Severity::Warning));
+        assert_eq!(diagnostics[0].message, "Unknown rule `any_currency`");
+        assert_eq!(
+            diagnostics[1].message,
+            "Unknown rule `duplicate_transactions`"
+        );
+        assert_eq!(
+            diagnostics[1].span.clone().map(|s| input[s].trim()),
+            Some(r#"option "rule.duplicate_transactions" "off""#)
+        );
+
+        // Rules of registered validators are known, even when they aren't built in.
+        assert_eq!(
+            run(&ValidationRunner::default().register(AnyCurrency), input)?,
+            vec![(codes::UNKNOWN_RULE, Severity::Warning)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ledger_balance_by_currency_and_day() -> Result<()> {
+        let input = r#"2020-01-02 transaction "Cents"
+  > 10.05 BRL equity:initial_import
+  < 10 BRL assets:cash
+2020-01-03 transaction "Dollars"
+  > 5 USD equity:initial_import
+  < 5 USD assets:dollars
+2020-01-04 transaction "Exchange"
+  > 5 USD assets:dollars
+  < 25 BRL assets:cash
+"#;
+        let (ledger, context) = compute_program(parse_string(Path::new("books.hta"), input)?)?;
+
+        let found = validate_credits_and_debits_balance(&ledger, &context.options)?
+            .into_iter()
+            .map(|t| {
+                let span = t.span.unwrap();
+                let text = input.chars().skip(span.start).take(span.len());
+
+                (t.message, text.collect::<String>().trim_end().to_string())
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "Books do not balance in `BRL` from 2020-01-02".to_string(),
+                    "> 10.05 BRL equity:initial_import\n  < 10 BRL assets:cash".to_string()
+                ),
+                (
+                    "Books do not balance in `USD` from 2020-01-04".to_string(),
+                    "> 5 USD assets:dollars".to_string()
+                ),
+            ]
+        );
+
+        // Days are checked with the same tolerance as single transactions.
+        let input = r#"option "default_tolerance" "0.01"
+2020-01-02 transaction "Rounded"
+  > 10.005 BRL equity:initial_import
+  < 10 BRL assets:cash
+"#;
+        let filename = Path::new("books.hta");
+        let (ledger, context) = compute_program(parse_string(filename, input)?)?;
+
+        assert!(validate_credits_and_debits_balance(&ledger, &context.options)?.is_empty());
+        assert!(TransactionBalance
+            .validate(filename, &ledger, &context)?
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_balances_by_currency() -> Result<()> {
+        let input = r#"2020-01-01 open assets:cash
+2020-01-01 open equity:initial_import
+2020-01-02 transaction "Reais"
+  > 100 BRL equity:initial_import
+  < 100 BRL assets:cash
+2020-01-02 transaction "Dollars"
+  > 10 USD equity:initial_import
+  < 10 USD assets:cash
+2020-01-03 balance assets:cash 100 BRL
+2020-01-03 balance assets:cash 10 USD
+2020-01-03 balance assets:cash 110 BRL
+"#;
+        let filename = Path::new("books.hta");
+        let (ledger, context) = compute_program(parse_string(filename, input)?)?;
+
+        let found = ledger
+            .validate_balances(
+                filename,
+                context.balance_verifications.clone(),
+                &context.options,
+            )?
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            vec![
+                "Balance for `assets:cash` on 2020-01-03 does not match, expected `110 BRL`, \
+                found 100.00 BRL"
+                    .to_string()
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_small_amounts_are_exact() -> Result<()> {
+        let input = r#"2020-01-01 open assets:cash BRL
+2020-01-01 open expenses:food
+2020-01-01 open equity:initial_import
+2020-01-02 transaction "Tenths"
+  > 0.3 BRL equity:initial_import
+  < 0.1 BRL assets:cash
+  < 0.2 BRL assets:cash
+2020-01-03 transaction "Tiny"
+  > 0.00001 BRL assets:cash
+  < 0.00001 BRL expenses:food
+2020-01-04 transaction "Half a cent"
+  > 10.005 BRL equity:initial_import
+  < 10 BRL assets:cash
+"#;
+        let (ledger, context) = compute_program(parse_string(Path::new("books.hta"), input)?)?;
+
+        assert_eq!(rows(&ledger)?.len(), 7);
+        assert_eq!(
+            validate_credits_and_debits_balance(&ledger, &context.options)?
+                .into_iter()
+                .map(|t| t.message)
+                .collect::<Vec<_>>(),
+            vec!["Books do not balance in `BRL` from 2020-01-04".to_string()]
+        );
+        assert_eq!(
+            run(&ValidationRunner::default(), input)?,
+            vec![
+                (codes::UNBALANCED_LEDGER, Severity::Error),
+                (codes::UNBALANCED_TRANSACTION, Severity::Error)
+            ]
+        );
+
+        // Transactions and days are only compared with a tolerance when the ledger sets one.
+        let tolerant = format!("option \"default_tolerance\" \"0.01\"\n{}", input);
+
+        assert_eq!(run(&ValidationRunner::default(), &tolerant)?, vec![]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicate_transactions() -> Result<()> {
+        let input = r#"option "duplicate_window" "2"
+2020-01-02 transaction "Market, downtown"
+  > 50 BRL assets:cash
+  < 50 BRL expenses:food
+2020-01-02 transaction "Coffee"
+  > 5 BRL assets:cash
+  < 5 BRL expenses:food
+2020-01-03 transaction "Coffee"
+  repeated: "Bought another one"
+  > 5 BRL assets:cash
+  < 5 BRL expenses:food
+2020-01-04 transaction "MARKET DOWNTOWN"
+  < 50 BRL expenses:food
+  > 50 BRL assets:cash
+2020-01-04 transaction "Market, downtown"
+  > 51 BRL assets:cash
+  < 51 BRL expenses:food
+2020-01-09 transaction "Market downtown"
+  > 50 BRL assets:cash
+  < 50 BRL expenses:food
+"#;
+        let filename = Path::new("books.hta");
+        let (ledger, context) = compute_program(parse_string(filename, input)?)?;
+        let diagnostics = DuplicateTransactions.validate(filename, &ledger, &context)?;
+
+        let line = |span: &Span| {
+            input
+                .chars()
+                .take(span.start)
+                .filter(|c| *c == '\n')
+                .count()
+                + 1
+        };
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "Transaction looks like a duplicate of the one on 2020-01-02"
+        );
+        assert_eq!(
+            diagnostics[0]
+                .labels
+                .iter()
+                .map(|l| line(&l.span))
+                .collect::<Vec<_>>(),
+            vec![3, 13]
+        );
+        assert_eq!(diagnostics[0].span.as_ref().map(line), Some(13));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_order_and_future_dates() -> Result<()> {
+        let input = r#"2021-03-01 open assets:bank BRL
+2021-03-02 note assets:bank "Opened"
+2012-03-04 note assets:bank "Typo"
+2021-03-04 note assets:bank "Fine"
+2021-03-14 note assets:bank "Scheduled"
+"#;
+        let runner = ValidationRunner::empty()
+            .register(DateOrder)
+            .register(FutureDates::default())
+            .replace(FutureDates::new(NaiveDate::from_ymd(2021, 3, 10)));
+
+        assert_eq!(runner.validators().count(), 2);
+        assert_eq!(
+            run(&runner, input)?,
+            vec![
+                (codes::OUT_OF_ORDER, Severity::Warning),
+                (codes::FUTURE_DATE, Severity::Warning),
+            ]
+        );
+
+        let strict = format!("option \"strict_date_order\" \"true\"\n{}", input);
+        assert_eq!(
+            run(&runner, &strict)?[0],
+            (codes::OUT_OF_ORDER, Severity::Error)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_abnormal_balances() -> Result<()> {
+        let input = r#"2021-01-01 open assets:bank:checking BRL
+  abnormal_balance: "Overdraft up to 500 BRL"
+2021-01-01 transaction "Salary"
+  > 100 BRL income:salary
+  < 100 BRL assets:cash
+2021-01-05 transaction "Market"
+  < 50 BRL assets:cash
+  > 50 BRL expenses:food
+2021-01-06 transaction "Deposit"
+  > 200 BRL assets:cash
+  < 200 BRL assets:bank:checking:savings
+2021-01-06 transaction "Cash withdrawal"
+  > 200 BRL assets:bank:checking
+  < 200 BRL assets:cash
+"#;
+        let filename = Path::new("books.hta");
+        let (ledger, context) = compute_program(parse_string(filename, input)?)?;
+        let diagnostics = ValidationRunner::empty()
+            .register(AbnormalBalances)
+            .run(filename, &ledger, &context)?;
+
+        let found = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.severity,
+                    d.message.clone(),
+                    d.span.clone().map(|s| input[s].trim_end().to_string()),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // Cash is only negative in the middle of a day, and the checking account is allowed to be.
+        assert_eq!(
+            found,
+            vec![(
+                Severity::Warning,
+                "Balance of `expenses:food` goes to the credit side on 2021-01-05, found -50.00 BRL"
+                    .to_string(),
+                Some("> 50 BRL expenses:food".to_string())
+            )]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_account_naming() -> Result<()> {
+        let input = r#"option "account_categories.expenses" "food home"
+option "account_pattern.assets" "^assets:(bank|cash)(:[a-z]+)*$"
+option "max_account_depth" "3"
+2021-01-01 open assets:bank:checking BRL
+2021-01-01 open assets:wallet BRL
+2021-01-05 transaction "Market"
+  > 50 BRL assets:bank:checking
+  < 50 BRL expenses:groceries
+2021-01-06 transaction "Market"
+  > 50 BRL assets:bank:checking
+  < 20 BRL expenses:groceries
+  < 30 BRL expenses:food:market:downtown
+"#;
+        let filename = Path::new("books.hta");
+        let (ledger, context) = compute_program(parse_string(filename, input)?)?;
+        let diagnostics = ValidationRunner::empty()
+            .register(AccountNaming)
+            .run(filename, &ledger, &context)?;
+
+        let found = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.message.clone(),
+                    d.span
+                        .clone()
+                        .and_then(|s| input[s].lines().next().map(String::from)),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "Account `assets:wallet` does not match the naming pattern, expected \
+                    `^assets:(bank|cash)(:[a-z]+)*$`"
+                        .to_string(),
+                    Some("2021-01-01 open assets:wallet BRL".to_string())
+                ),
+                (
+                    "`groceries` is not a category allowed under `expenses`, expected `food home`"
+                        .to_string(),
+                    Some("< 50 BRL expenses:groceries".to_string())
+                ),
+                (
+                    "Account `expenses:food:market:downtown` is nested deeper than 3 levels"
+                        .to_string(),
+                    Some("< 30 BRL expenses:food:market:downtown".to_string())
+                ),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_transaction_shapes() -> Result<()> {
+        let input = r#"2021-01-05 transaction "Forgotten"
+  < 50 BRL expenses:food
+2021-01-05 transaction "Not filled in"
+  > 0 BRL assets:cash
+  < 0 BRL expenses:food
+2021-01-06 transaction "Wrong sign"
+  < 50 BRL assets:cash
+  < 50 BRL expenses:food
+"#;
+        let runner = ValidationRunner::empty()
+            .register(SingleMovements)
+            .register(ZeroAmounts)
+            .register(OneSidedTransactions);
+
+        assert_eq!(
+            run(&runner, input)?,
+            vec![
+                (codes::SINGLE_MOVEMENT, Severity::Error),
+                (codes::ZERO_AMOUNT, Severity::Warning),
+                (codes::ZERO_AMOUNT, Severity::Warning),
+                (codes::ONE_SIDED_TRANSACTION, Severity::Error),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_similar_descriptions() {
+        assert!(similar_descriptions("Market, downtown", "MARKET DOWNTOWN"));
+        assert!(similar_descriptions("Uber trip 1234", "Uber trip 1235"));
+        assert!(!similar_descriptions("Uber trip", "Rent"));
+        assert!(similar_descriptions("", ""));
+    }
 }